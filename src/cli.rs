@@ -1,23 +1,50 @@
 use std::path::PathBuf;
 
+use clap::builder::RangedU64ValueParser;
 use clap::{Args, Parser, Subcommand};
 use clap_complete::Shell;
 
+use crate::core::{ConflictStrategy, EditorFormat, PostVerifyMode};
+use crate::discogs::create_tag::{FeatHandling, TagCase, DEFAULT_GENRE_SEPARATOR};
+use crate::discogs::matcher::{
+    MixedAlbumFolders, DEFAULT_CATALOG_NUMBER_PATTERN, DEFAULT_DURATION_TOLERANCE,
+    DEFAULT_FOLDER_TAGS_PATTERN, DEFAULT_MAX_EXTRA_FILES, DEFAULT_MIN_TRACK_COUNT,
+};
+use crate::music_file::{
+    ArtistFolderFrom, OutputCase, OutputStructure, DEFAULT_DISC_SUBFOLDER_TEMPLATE,
+    DEFAULT_SINGLES_TEMPLATE,
+};
+use crate::util::console::OutputFormat;
+use crate::util::string_extensions::SimilarityAlgorithm;
+
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 pub struct Cli {
     #[clap(long)]
     pub discogs_token: Option<String>,
 
+    #[clap(long, requires = "discogs_token")]
+    pub save_token: bool,
+
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output_format: OutputFormat,
+
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
     #[clap(subcommand)]
     pub command: Command,
 }
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Command {
     GenerateCompletions(GenerateCompletionsArgs),
     Import(ImportArgs),
     AddCovers(AddCoversArguments),
+    Move(MoveArgs),
+    CheckToken,
+    CleanBackups(CleanBackupsArgs),
 }
 
 #[derive(Args)]
@@ -32,7 +59,7 @@ pub struct ImportArgs {
     pub from: Vec<PathBuf>,
 
     #[clap(long)]
-    pub to: Option<PathBuf>,
+    pub to: Vec<PathBuf>,
 
     #[clap(long, default_value_t = true, action = clap::ArgAction::Set)]
     pub clean_target_folders: bool,
@@ -45,10 +72,281 @@ pub struct ImportArgs {
 
     #[clap(long)]
     pub discogs_release_id: Option<String>,
+
+    #[clap(long, num_args = 0..=1, default_missing_value = DEFAULT_CATALOG_NUMBER_PATTERN)]
+    pub catalog_number_pattern: Option<String>,
+
+    #[clap(long)]
+    pub cover_max_bytes: Option<u64>,
+
+    #[clap(long)]
+    pub force: bool,
+
+    #[clap(long)]
+    pub default_album_artist: Option<String>,
+
+    #[clap(long)]
+    pub infer_track_from_filename: bool,
+
+    #[clap(long)]
+    pub map_genre: Option<PathBuf>,
+
+    #[clap(long, default_value_t = DEFAULT_DURATION_TOLERANCE.as_secs())]
+    pub duration_tolerance: u64,
+
+    #[clap(long)]
+    pub inbox_template: Option<String>,
+
+    #[clap(long, default_value_t = DEFAULT_MAX_EXTRA_FILES)]
+    pub max_extra_files: usize,
+
+    #[clap(long, value_enum)]
+    pub matcher: Option<SimilarityAlgorithm>,
+
+    #[clap(long)]
+    pub no_transliterate: bool,
+
+    #[clap(long, value_enum, default_value_t = ConflictStrategy::Overwrite)]
+    pub on_conflict: ConflictStrategy,
+
+    #[clap(long)]
+    pub tracks: Option<String>,
+
+    #[clap(long, value_delimiter = ',')]
+    pub prefer_local: Vec<String>,
+
+    #[clap(long)]
+    pub no_total_tracks: bool,
+
+    #[clap(long, num_args = 0..=1, default_missing_value = DEFAULT_FOLDER_TAGS_PATTERN)]
+    pub album_artist_from_folder: Option<String>,
+
+    #[clap(long, hide = true)]
+    pub print_release_json: bool,
+
+    #[clap(long)]
+    pub set_genre: Option<String>,
+
+    #[clap(long)]
+    pub require_year: bool,
+
+    #[clap(long)]
+    pub remember_original_name: bool,
+
+    #[clap(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub cover_file: bool,
+
+    #[clap(long)]
+    pub embed_covers: bool,
+
+    #[clap(long, num_args = 0..=1, default_missing_value = DEFAULT_SINGLES_TEMPLATE)]
+    pub singles_template: Option<String>,
+
+    #[clap(long)]
+    pub keep_unmatched_tags: bool,
+
+    #[clap(long)]
+    pub review_grouping: bool,
+
+    #[clap(long)]
+    pub replace_existing_library: bool,
+
+    #[clap(long)]
+    pub detect_swapped_tags: bool,
+
+    #[clap(long)]
+    pub dump_unmatched: Option<PathBuf>,
+
+    #[clap(long, value_enum, default_value_t = OutputStructure::ByArtist)]
+    pub output_structure: OutputStructure,
+
+    #[clap(long)]
+    pub label_fallback_to_artist: bool,
+
+    #[clap(long, default_value_t = DEFAULT_MIN_TRACK_COUNT)]
+    pub min_track_count: usize,
+
+    #[clap(long, default_value = DEFAULT_GENRE_SEPARATOR)]
+    pub genre_separator: String,
+
+    #[clap(long)]
+    pub resume_from: Option<PathBuf>,
+
+    #[clap(long)]
+    pub embed_lyrics_from: Option<PathBuf>,
+
+    #[clap(long)]
+    pub prefer_country: Option<String>,
+
+    #[clap(long, num_args = 0..=1, default_missing_value = DEFAULT_DISC_SUBFOLDER_TEMPLATE)]
+    pub disc_subfolders: Option<String>,
+
+    #[clap(long, value_enum, default_value_t = FeatHandling::Keep)]
+    pub feat_handling: FeatHandling,
+
+    #[clap(long)]
+    pub strip_frame: Vec<String>,
+
+    #[clap(long)]
+    pub preserve_frame: Vec<String>,
+
+    #[clap(long)]
+    pub preserve_all_custom: bool,
+
+    #[clap(long, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_depth: Option<usize>,
+
+    #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "warn")]
+    pub post_verify: Option<PostVerifyMode>,
+
+    #[clap(long)]
+    pub cover_any_type: bool,
+
+    #[clap(long, value_enum, default_value_t = TagCase::Keep)]
+    pub tag_case: TagCase,
+
+    #[clap(long)]
+    pub tag_case_unmatched: bool,
+
+    #[clap(long, value_enum, default_value_t = EditorFormat::Kv)]
+    pub editor_format: EditorFormat,
+
+    #[clap(long, value_delimiter = ',')]
+    pub cover_sidecar_formats: Vec<String>,
+
+    #[clap(long, conflicts_with = "keep_empty_dirs")]
+    pub remove_empty_dirs: bool,
+
+    #[clap(long)]
+    pub keep_empty_dirs: bool,
+
+    #[clap(long)]
+    pub prefer_existing_cover: bool,
+
+    #[clap(long)]
+    pub assign_by_filename_order: bool,
+
+    #[clap(long)]
+    pub recount_tracks: bool,
+
+    #[clap(long)]
+    pub from_playlist: Option<PathBuf>,
+
+    #[clap(long, value_enum, default_value_t = MixedAlbumFolders::Keep)]
+    pub mixed_album_folders: MixedAlbumFolders,
+
+    #[clap(long, value_enum, default_value_t = OutputCase::Preserve)]
+    pub output_case: OutputCase,
+
+    #[clap(long)]
+    pub discogs_format_filter: Option<String>,
+
+    #[clap(long)]
+    pub trash: bool,
+
+    #[clap(long)]
+    pub all_covers: bool,
+
+    #[clap(long)]
+    pub report_unmatched: Option<PathBuf>,
+
+    #[clap(long)]
+    pub year_from_folder: bool,
+
+    #[clap(long)]
+    pub dump_search_queries: bool,
+
+    #[clap(long, value_enum, default_value_t = ArtistFolderFrom::AlbumArtist)]
+    pub artist_folder_from: ArtistFolderFrom,
+
+    #[clap(long)]
+    pub concurrency_safe: bool,
+
+    #[clap(long)]
+    pub alpha_buckets: bool,
+
+    #[clap(long, conflicts_with = "output_structure")]
+    pub mirror: bool,
+
+    #[clap(long)]
+    pub write_id3v1: bool,
+
+    #[clap(long)]
+    pub include_remixers: bool,
 }
 
 #[derive(Args)]
 pub struct AddCoversArguments {
     #[clap()]
     pub to: PathBuf,
+
+    #[clap(long)]
+    pub cover_max_bytes: Option<u64>,
+
+    #[clap(long)]
+    pub cover_any_type: bool,
+
+    #[clap(long, value_delimiter = ',')]
+    pub cover_sidecar_formats: Vec<String>,
+
+    #[clap(long)]
+    pub all_covers: bool,
+}
+
+#[derive(Args)]
+pub struct MoveArgs {
+    #[clap(long, num_args = 1..)]
+    pub from: Vec<PathBuf>,
+
+    #[clap(long)]
+    pub to: PathBuf,
+
+    #[clap(long)]
+    pub keep_tags: bool,
+
+    #[clap(long)]
+    pub default_album_artist: Option<String>,
+
+    #[clap(long)]
+    pub infer_track_from_filename: bool,
+
+    #[clap(long, value_enum, default_value_t = ConflictStrategy::Overwrite)]
+    pub on_conflict: ConflictStrategy,
+
+    #[clap(long)]
+    pub remember_original_name: bool,
+
+    #[clap(long)]
+    pub year_from_folder: bool,
+
+    #[clap(long, value_enum, default_value_t = ArtistFolderFrom::AlbumArtist)]
+    pub artist_folder_from: ArtistFolderFrom,
+
+    #[clap(long)]
+    pub concurrency_safe: bool,
+
+    #[clap(long)]
+    pub alpha_buckets: bool,
+}
+
+#[derive(Args)]
+pub struct CleanBackupsArgs {
+    #[clap()]
+    pub to: PathBuf,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbose_is_accepted_after_subcommand() {
+        for args in [
+            ["music-files-organizer", "-vv", "check-token"],
+            ["music-files-organizer", "check-token", "-vv"],
+        ] {
+            assert_eq!(Cli::try_parse_from(args).unwrap().verbose, 2);
+        }
+    }
 }
@@ -3,16 +3,33 @@ use anyhow::Result;
 use AllowedChangeType::Covers;
 
 use crate::cli::AddCoversArguments;
-use crate::core::{AllowedChangeType, Args, work};
+use crate::core::{work, AllowedChangeType, Args, ConflictStrategy, CoverArgs, EditorFormat};
 
 pub fn add_covers(args: AddCoversArguments, discogs_token: Option<String>) -> Result<()> {
     work(Args {
-        input_paths: vec![args.to],
-        output_path: None,
+        input_output_paths: vec![(args.to, None)],
         allowed_change_types: vec![Covers],
+        no_source_cleanup_paths: vec![],
         allow_questions: false,
         chunk_size: Some(1),
+        max_depth: None,
+        use_discogs: true,
         discogs_token,
-        discogs_release_id: None,
+        force: false,
+        on_conflict: ConflictStrategy::Overwrite,
+        editor_format: EditorFormat::Kv,
+        concurrency_safe: false,
+        matching: Default::default(),
+        tagging: Default::default(),
+        layout: Default::default(),
+        covers: CoverArgs {
+            max_bytes: args.cover_max_bytes,
+            write_file: true,
+            any_type: args.cover_any_type,
+            all_covers: args.all_covers,
+            sidecar_names: args.cover_sidecar_formats,
+            ..Default::default()
+        },
+        cleanup: Default::default(),
     })
 }
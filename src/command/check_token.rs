@@ -0,0 +1,15 @@
+use anyhow::Result;
+
+use crate::console_print;
+use crate::discogs::matcher::{DiscogsMatcher, DiscogsMatcherOptions};
+use crate::util::console_styleable::ConsoleStyleable;
+
+pub fn check_token(discogs_token: Option<String>) -> Result<()> {
+    let discogs_matcher =
+        DiscogsMatcher::with_optional_token(&discogs_token, DiscogsMatcherOptions::default())?;
+    console_print!(
+        "Discogs token is valid, authenticated as {}",
+        discogs_matcher.check_token()?.tag_styled()
+    );
+    Ok(())
+}
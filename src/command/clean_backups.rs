@@ -0,0 +1,37 @@
+use std::fs;
+
+use anyhow::Result;
+use dialoguer::Confirm;
+
+use crate::cli::CleanBackupsArgs;
+use crate::console_print;
+use crate::core::BACKUP_FOLDER_PREFIX;
+use crate::util::console;
+use crate::util::console_styleable::ConsoleStyleable;
+use crate::util::path_extensions::PathExtensions;
+
+pub fn clean_backups(args: CleanBackupsArgs) -> Result<()> {
+    for entry in fs::read_dir(&args.to)? {
+        let path = entry?.path();
+        if !path.is_dir() || !path.file_name_or_empty().starts_with(BACKUP_FOLDER_PREFIX) {
+            continue;
+        }
+        let prompt = format!(
+            "Do you wish to remove backup {}?",
+            path.display().path_styled()
+        );
+        console::get().ensure_interactive(&prompt)?;
+        if Confirm::new()
+            .with_prompt(prompt)
+            .default(false)
+            .show_default(true)
+            .wait_for_newline(true)
+            .interact()?
+        {
+            fs::remove_dir_all(&path)?;
+            console_print!("Removed {}", path.display().path_styled());
+        }
+    }
+
+    Ok(())
+}
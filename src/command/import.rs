@@ -1,18 +1,146 @@
-use anyhow::Result;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
 
 use AllowedChangeType::{Covers, MusicFiles, SourceCleanup, TargetCleanup};
 
 use crate::cli::ImportArgs;
-use crate::core::{AllowedChangeType, Args, work};
+use crate::core::{
+    work, AllowedChangeType, Args, CleanupArgs, CoverArgs, LayoutArgs, MatchingArgs, TaggingArgs,
+};
+use crate::music_file::OutputStructure;
+use crate::util::playlist;
+use crate::util::console;
+use crate::util::console::OutputFormat;
+use crate::util::string_extensions::SimilarityOptions;
 
 pub fn import(args: ImportArgs, discogs_token: Option<String>) -> Result<()> {
+    let playlist_paths = args
+        .from_playlist
+        .as_deref()
+        .map(playlist::read_m3u)
+        .transpose()?;
     work(Args {
-        input_paths: args.from,
-        output_path: args.to,
+        input_output_paths: input_output_paths(args.from, args.to, playlist_paths.clone())?,
         allowed_change_types: vec![MusicFiles, Covers, SourceCleanup, TargetCleanup],
-        allow_questions: true,
+        // Playlist picks single files so the rest of their source folders is not ours to clean
+        no_source_cleanup_paths: playlist_paths.unwrap_or_default(),
+        // Frontends reading JSON can't answer prompts, defaults are taken instead
+        allow_questions: console::get().output_format() != OutputFormat::Json,
         chunk_size: args.chunk_size,
+        max_depth: args.max_depth,
+        use_discogs: true,
         discogs_token,
-        discogs_release_id: args.discogs_release_id,
+        force: args.force,
+        on_conflict: args.on_conflict,
+        editor_format: args.editor_format,
+        concurrency_safe: args.concurrency_safe,
+        matching: MatchingArgs {
+            discogs_release_id: args.discogs_release_id,
+            catalog_number_pattern: args.catalog_number_pattern,
+            duration_tolerance: Duration::from_secs(args.duration_tolerance),
+            max_extra_files: args.max_extra_files,
+            similarity: SimilarityOptions {
+                algorithm: args.matcher,
+                transliterate: !args.no_transliterate,
+            },
+            tracks: args.tracks,
+            folder_tags_pattern: args.album_artist_from_folder,
+            infer_track_from_filename: args.infer_track_from_filename,
+            year_from_folder: args.year_from_folder,
+            require_year: args.require_year,
+            print_release_json: args.print_release_json,
+            review_grouping: args.review_grouping,
+            detect_swapped_tags: args.detect_swapped_tags,
+            min_track_count: args.min_track_count,
+            resume_from: args.resume_from,
+            prefer_country: args.prefer_country,
+            assign_by_filename_order: args.assign_by_filename_order,
+            mixed_album_folders: args.mixed_album_folders,
+            discogs_format_filter: args.discogs_format_filter,
+            dump_search_queries: args.dump_search_queries,
+            report_unmatched_path: args.report_unmatched,
+        },
+        tagging: TaggingArgs {
+            genre_map_path: args.map_genre,
+            fixed_genre: args.set_genre,
+            genre_separator: args.genre_separator,
+            prefer_local_frames: args.prefer_local,
+            no_total_tracks: args.no_total_tracks,
+            keep_unmatched_tags: args.keep_unmatched_tags,
+            remember_original_name: args.remember_original_name,
+            feat_handling: args.feat_handling,
+            include_remixers: args.include_remixers,
+            tag_case: args.tag_case,
+            tag_case_unmatched: args.tag_case_unmatched,
+            recount_tracks: args.recount_tracks,
+            strip_frames: args.strip_frame,
+            preserve_frames: args.preserve_frame,
+            preserve_all_custom: args.preserve_all_custom,
+            embed_lyrics_from: args.embed_lyrics_from,
+            write_id3v1: args.write_id3v1,
+            post_verify: args.post_verify,
+        },
+        layout: LayoutArgs {
+            output_structure: if args.mirror {
+                OutputStructure::Mirror
+            } else {
+                args.output_structure
+            },
+            output_case: args.output_case,
+            default_album_artist: args.default_album_artist,
+            artist_folder_from: args.artist_folder_from,
+            label_fallback_to_artist: args.label_fallback_to_artist,
+            alpha_buckets: args.alpha_buckets,
+            inbox_template: args.inbox_template,
+            singles_template: args.singles_template,
+            disc_subfolder_template: args.disc_subfolders,
+        },
+        covers: CoverArgs {
+            max_bytes: args.cover_max_bytes,
+            write_file: args.cover_file,
+            embed: args.embed_covers,
+            any_type: args.cover_any_type,
+            all_covers: args.all_covers,
+            sidecar_names: args.cover_sidecar_formats,
+            prefer_existing: args.prefer_existing_cover,
+        },
+        cleanup: CleanupArgs {
+            backup_existing: args.replace_existing_library,
+            dump_unmatched_path: args.dump_unmatched,
+            remove_empty_dirs: match (args.remove_empty_dirs, args.keep_empty_dirs) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            trash: args.trash,
+        },
     })
 }
+
+fn input_output_paths(
+    mut from: Vec<PathBuf>,
+    to: Vec<PathBuf>,
+    playlist_paths: Option<Vec<PathBuf>>,
+) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    // Playlist entries are fed as individual files, so the folder walk is skipped for them
+    if let Some(playlist_paths) = playlist_paths {
+        if to.len() > 1 {
+            bail!("Expected single --to path when importing from playlist")
+        }
+        from.extend(playlist_paths);
+    }
+
+    if to.len() <= 1 {
+        let output_path = to.into_iter().next();
+        Ok(from
+            .into_iter()
+            .map(|input_path| (input_path, output_path.clone()))
+            .collect())
+    } else if to.len() == from.len() {
+        Ok(from.into_iter().zip(to.into_iter().map(Some)).collect())
+    } else {
+        bail!("Expected either single --to path or one --to path per --from path")
+    }
+}
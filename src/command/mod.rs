@@ -1,3 +1,6 @@
 pub mod add_covers;
+pub mod check_token;
+pub mod clean_backups;
 pub mod generate_completions;
 pub mod import;
+pub mod move_files;
@@ -0,0 +1,51 @@
+use anyhow::Result;
+
+use AllowedChangeType::{MusicFiles, SourceCleanup, TargetCleanup};
+
+use crate::cli::MoveArgs;
+use crate::core::{
+    work, AllowedChangeType, Args, EditorFormat, LayoutArgs, MatchingArgs, TaggingArgs,
+};
+use crate::util::console;
+use crate::util::console::OutputFormat;
+
+pub fn move_files(args: MoveArgs) -> Result<()> {
+    let to = args.to;
+    work(Args {
+        input_output_paths: args
+            .from
+            .into_iter()
+            .map(|input_path| (input_path, Some(to.clone())))
+            .collect(),
+        allowed_change_types: vec![MusicFiles, SourceCleanup, TargetCleanup],
+        no_source_cleanup_paths: vec![],
+        // Frontends reading JSON can't answer prompts, defaults are taken instead
+        allow_questions: console::get().output_format() != OutputFormat::Json,
+        chunk_size: None,
+        max_depth: None,
+        use_discogs: false,
+        discogs_token: None,
+        force: false,
+        on_conflict: args.on_conflict,
+        editor_format: EditorFormat::Kv,
+        concurrency_safe: args.concurrency_safe,
+        matching: MatchingArgs {
+            infer_track_from_filename: args.infer_track_from_filename,
+            year_from_folder: args.year_from_folder,
+            ..Default::default()
+        },
+        tagging: TaggingArgs {
+            keep_unmatched_tags: args.keep_tags,
+            remember_original_name: args.remember_original_name,
+            ..Default::default()
+        },
+        layout: LayoutArgs {
+            default_album_artist: args.default_album_artist,
+            artist_folder_from: args.artist_folder_from,
+            alpha_buckets: args.alpha_buckets,
+            ..Default::default()
+        },
+        covers: Default::default(),
+        cleanup: Default::default(),
+    })
+}
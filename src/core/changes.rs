@@ -1,25 +1,33 @@
-use std::collections::HashSet;
-use std::fmt::Write;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter, Write};
 use std::fs;
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use dialoguer::Editor;
 use itertools::Itertools;
 use regex::Regex;
 use reqwest::Url;
 
-use crate::console_print;
-use crate::core::AllowedChangeType;
-use crate::discogs::create_tag::{create_tag_from_discogs_data, strip_redundant_fields};
+use crate::{console_print, console_warn};
+use crate::core::{AllowedChangeType, EditorFormat, OutputPaths, LOCK_FILE_NAME};
+use crate::discogs::create_tag::{
+    create_tag_from_discogs_data, strip_redundant_fields, CreateTagOptions, ORIGINAL_FILENAME_TAG,
+};
 use crate::discogs::matcher::DiscogsReleaseMatchResult;
 use crate::discogs::matcher::DiscogsReleaseMatchResult::{Matched, Unmatched};
-use crate::discogs::model::refined::DiscogsRelease;
-use crate::music_file::{music_file_name_for, MusicFile, relative_path_for};
+use crate::discogs::model::refined::{DiscogsRelease, DiscogsReleaseKind};
+use crate::music_file::{
+    disc_subfolder_for, music_file_name_for, relative_path_for, MusicFile, MusicPathOptions,
+};
 use crate::tag::frame::{FrameContent, FrameId};
+use crate::tag::PictureType;
+use crate::util::console;
+use crate::util::console_event::{ChangeKind, ConsoleEvent, FrameDiff};
 use crate::util::console_styleable::ConsoleStyleable;
+use crate::util::image_compression;
 use crate::util::path_extensions::PathExtensions;
 
 pub struct ChangeList<'a> {
@@ -35,10 +43,42 @@ pub struct MusicFileChange<'a> {
     discogs_release: Option<&'a DiscogsRelease>,
 }
 
-#[derive(Hash, PartialEq, Eq)]
+impl MusicFileChange<'_> {
+    fn is_noop(&self) -> bool {
+        let source_tag = &self.source.tag;
+        let target_tag = &self.target.tag;
+        self.source.file_path == self.target.file_path
+            && source_tag
+                .frame_ids()
+                .iter()
+                .chain(target_tag.frame_ids().iter())
+                .all(|frame_id| {
+                    source_tag.frame_content(frame_id) == target_tag.frame_content(frame_id)
+                })
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
 pub struct CoverChange {
     pub path: PathBuf,
-    pub uri: String,
+    pub source: CoverSource,
+    pub sidecar_paths: Vec<PathBuf>,
+    pub picture_type: PictureType,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub enum CoverSource {
+    Download { uri: String },
+    Copy { path: PathBuf },
+}
+
+impl Display for CoverSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoverSource::Download { uri } => write!(f, "{}", uri),
+            CoverSource::Copy { path } => write!(f, "{}", path.display()),
+        }
+    }
 }
 
 #[derive(Clone, Hash, PartialEq, Eq)]
@@ -48,20 +88,35 @@ pub struct Cleanup {
 
 pub fn calculate_changes<'a>(
     discogs_match_results: &'a [DiscogsReleaseMatchResult],
-    output_path: &Option<PathBuf>,
+    output_paths: &OutputPaths,
+    path_options: &MusicPathOptions,
+    tag_options: &CreateTagOptions,
     allowed_change_types: &[AllowedChangeType],
+    no_source_cleanup_paths: &[PathBuf],
+    force: bool,
 ) -> Result<ChangeList<'a>> {
-    let file_changes = get_file_changes(discogs_match_results, output_path)?;
-    let cover_changes = get_cover_changes(&file_changes)?;
+    let file_changes = get_file_changes(
+        discogs_match_results,
+        output_paths,
+        path_options,
+        tag_options,
+    )?;
+    warn_about_divergent_albums(&file_changes);
+    let cover_changes = get_cover_changes(&file_changes, path_options)?;
     let cleanup_changes = get_cleanup_changes(
         &file_changes,
         &cover_changes,
         allowed_change_types.contains(&AllowedChangeType::SourceCleanup),
         allowed_change_types.contains(&AllowedChangeType::TargetCleanup),
+        no_source_cleanup_paths,
     )?;
     Ok(ChangeList {
         music_files: if allowed_change_types.contains(&AllowedChangeType::MusicFiles) {
+            // No-op changes are dropped only now as covers and cleanups still depend on them
             file_changes
+                .into_iter()
+                .filter(|change| force || !change.is_noop())
+                .collect_vec()
         } else {
             vec![]
         },
@@ -76,30 +131,46 @@ pub fn calculate_changes<'a>(
 
 pub fn edit_changes<'a>(
     changes: ChangeList<'a>,
-    output_path: &Option<PathBuf>,
+    output_paths: &OutputPaths,
+    path_options: &MusicPathOptions,
+    editor_format: EditorFormat,
 ) -> Result<ChangeList<'a>> {
-    const TRACK_DELIMITER: &str = "--------------------------";
-    let line_pattern: Regex = Regex::new(r"^(.+?): (.*)$")?;
-    let mut editor_prompt = String::new();
-
-    for music_file in &changes.music_files {
-        let tag = &music_file.target.tag;
-        for frame_id in &tag.frame_ids() {
-            let frame_content = tag.frame_content(frame_id);
-            writeln!(
-                &mut editor_prompt,
-                "{}: {}",
-                frame_id,
-                frame_content.map(|v| v.to_string()).unwrap_or_default()
-            )?;
-        }
+    let tracks_frames = changes
+        .music_files
+        .iter()
+        .map(|music_file| {
+            let tag = &music_file.target.tag;
+            let mut frame_ids = tag.frame_ids();
+            // Let user explicitly set disc numbering even for single-disc releases
+            for frame_id in [FrameId::Disc, FrameId::TotalDiscs] {
+                if tag.frame_content(&frame_id).is_none() {
+                    frame_ids.push(frame_id);
+                }
+            }
+            frame_ids
+                .iter()
+                .map(|frame_id| {
+                    let frame_content = tag.frame_content(frame_id);
+                    (
+                        frame_id.to_string(),
+                        frame_content.map(|v| v.to_string()).unwrap_or_default(),
+                    )
+                })
+                .collect_vec()
+        })
+        .collect_vec();
 
-        editor_prompt.push_str(TRACK_DELIMITER);
-        editor_prompt.push('\n');
-    }
+    let editor_prompt = match editor_format {
+        EditorFormat::Kv => kv_editor_prompt(&tracks_frames)?,
+        EditorFormat::Toml => toml_editor_prompt(&tracks_frames)?,
+    };
 
     if let Some(edited) = Editor::new().edit(&editor_prompt)? {
-        let mut edited_lines = edited.lines();
+        let mut edited_tracks = match editor_format {
+            EditorFormat::Kv => parse_kv_editor_output(&edited)?,
+            EditorFormat::Toml => parse_toml_editor_output(&edited)?,
+        }
+        .into_iter();
         let mut new_music_file_changes: Vec<MusicFileChange> = Vec::new();
 
         for music_file in changes.music_files {
@@ -107,31 +178,28 @@ pub fn edit_changes<'a>(
             let mut new_tag = old_tag.clone();
             new_tag.clear();
 
-            loop {
-                let line = edited_lines
-                    .next()
-                    .context("Failed to find meta for track")?;
-
-                if line == TRACK_DELIMITER {
-                    break;
-                }
+            let edited_frames = edited_tracks
+                .next()
+                .context("Failed to find meta for track")?;
 
-                let invalid_line_context = || format!("Invalid line: {}", line);
-                let captures = line_pattern
-                    .captures(line)
-                    .with_context(invalid_line_context)?;
-                let frame_id_as_string =
-                    captures.get(1).with_context(invalid_line_context)?.as_str();
-                let frame_content_as_string =
-                    captures.get(2).with_context(invalid_line_context)?.as_str();
+            for (frame_id_as_string, frame_content_as_string) in &edited_frames {
                 let frame_id = FrameId::from_str(frame_id_as_string)?;
 
+                if frame_content_as_string.is_empty() {
+                    new_tag.set_frame(&frame_id, None)?;
+                    continue;
+                }
+
                 let frame_content = match frame_id {
                     FrameId::Title
                     | FrameId::Album
                     | FrameId::AlbumArtist
                     | FrameId::Artist
                     | FrameId::Genre
+                    | FrameId::EncodedBy
+                    | FrameId::Isrc
+                    | FrameId::AlbumSort
+                    | FrameId::TitleSort
                     | FrameId::CustomText { .. } => {
                         FrameContent::Str(frame_content_as_string.to_owned())
                     }
@@ -142,10 +210,16 @@ pub fn edit_changes<'a>(
                 };
                 new_tag.set_frame(&frame_id, Some(frame_content))?;
             }
-            let file_path = if let Some(output_path) = &output_path {
+            let file_path = if let Some(output_path) =
+                output_paths.output_path_for(&music_file.source.file_path)
+            {
                 output_path.join(relative_path_for(
                     new_tag.deref(),
                     music_file.target.file_path.extension_or_empty(),
+                    is_single(music_file.discogs_release),
+                    music_file.discogs_release.and_then(|v| v.label.as_deref()),
+                    &output_paths.source_relative_path_for(&music_file.source.file_path),
+                    path_options,
                 )?)
             } else {
                 music_file
@@ -155,6 +229,7 @@ pub fn edit_changes<'a>(
                     .join(music_file_name_for(
                         new_tag.deref(),
                         music_file.target.file_path.extension_or_empty(),
+                        path_options.output_case,
                     )?)
             };
 
@@ -177,6 +252,106 @@ pub fn edit_changes<'a>(
     }
 }
 
+const KV_TRACK_DELIMITER: &str = "--------------------------";
+const TOML_TRACKS_KEY: &str = "track";
+
+fn kv_editor_prompt(tracks_frames: &[Vec<(String, String)>]) -> Result<String> {
+    let mut editor_prompt = String::new();
+    for frames in tracks_frames {
+        for (frame_id, frame_content) in frames {
+            writeln!(&mut editor_prompt, "{}: {}", frame_id, frame_content)?;
+        }
+        editor_prompt.push_str(KV_TRACK_DELIMITER);
+        editor_prompt.push('\n');
+    }
+    Ok(editor_prompt)
+}
+
+fn parse_kv_editor_output(edited: &str) -> Result<Vec<Vec<(String, String)>>> {
+    let line_pattern: Regex = Regex::new(r"^(.+?): ?(.*)$")?;
+    let mut tracks_frames = Vec::new();
+    let mut frames = Vec::new();
+    for line in edited.lines() {
+        if line == KV_TRACK_DELIMITER {
+            tracks_frames.push(std::mem::take(&mut frames));
+            continue;
+        }
+
+        let (frame_id, frame_content) = split_kv_line(line, &line_pattern)
+            .with_context(|| format!("Invalid line: {}", line))?;
+        frames.push((frame_id.to_owned(), frame_content.to_owned()));
+    }
+    Ok(tracks_frames)
+}
+
+// Known frame names are matched first so colons later in the line always belong to the value
+fn split_kv_line<'l>(line: &'l str, line_pattern: &Regex) -> Option<(&'l str, &'l str)> {
+    let standard = FrameId::STANDARD.iter().find_map(|frame_id| {
+        let frame_id = frame_id.to_string();
+        let rest = line.strip_prefix(frame_id.as_str())?.strip_prefix(':')?;
+        let value = rest.strip_prefix(' ').unwrap_or(rest);
+        Some((&line[..frame_id.len()], value))
+    });
+    standard.or_else(|| {
+        // Custom frame prefix has a colon of its own, so the key starts after it
+        let prefix_len = FrameId::custom_key(line).map_or(0, |key| line.len() - key.len());
+        let captures = line_pattern.captures(&line[prefix_len..])?;
+        Some((
+            &line[..prefix_len + captures.get(1)?.end()],
+            captures.get(2)?.as_str(),
+        ))
+    })
+}
+
+// TOML quotes keys and values so colons, equals signs and newlines survive the round trip
+fn toml_editor_prompt(tracks_frames: &[Vec<(String, String)>]) -> Result<String> {
+    let tracks = tracks_frames
+        .iter()
+        .map(|frames| {
+            toml::Value::Table(
+                frames
+                    .iter()
+                    .map(|(frame_id, frame_content)| {
+                        (
+                            frame_id.to_owned(),
+                            toml::Value::String(frame_content.to_owned()),
+                        )
+                    })
+                    .collect(),
+            )
+        })
+        .collect();
+    let mut document = toml::Table::new();
+    document.insert(TOML_TRACKS_KEY.to_owned(), toml::Value::Array(tracks));
+    Ok(toml::to_string(&document)?)
+}
+
+fn parse_toml_editor_output(edited: &str) -> Result<Vec<Vec<(String, String)>>> {
+    let document = edited.parse::<toml::Table>()?;
+    let Some(toml::Value::Array(tracks)) = document.get(TOML_TRACKS_KEY) else {
+        bail!("Failed to find \"{}\" array of tables", TOML_TRACKS_KEY);
+    };
+    tracks
+        .iter()
+        .map(|track| {
+            let toml::Value::Table(frames) = track else {
+                bail!("Invalid track: {}", track);
+            };
+            frames
+                .iter()
+                .map(|(frame_id, frame_content)| {
+                    let frame_content = match frame_content {
+                        toml::Value::String(value) => value.to_owned(),
+                        toml::Value::Integer(value) => value.to_string(),
+                        value => bail!("Invalid value for {}: {}", frame_id, value),
+                    };
+                    Ok((frame_id.to_owned(), frame_content))
+                })
+                .collect()
+        })
+        .collect()
+}
+
 pub fn print_changes_details(changes: &ChangeList) {
     let mut step_number = 1u32;
 
@@ -236,10 +411,14 @@ pub fn print_changes_details(changes: &ChangeList) {
     }
 
     for change in &changes.covers {
+        let action = match change.source {
+            CoverSource::Download { .. } => "Download",
+            CoverSource::Copy { .. } => "Copy",
+        };
         console_print!(
             "{:02}. {} cover to {}",
             step_number,
-            "Download".styled().green(),
+            action.styled().green(),
             change.path.display().path_styled(),
         );
         step_number += 1;
@@ -256,9 +435,51 @@ pub fn print_changes_details(changes: &ChangeList) {
     }
 }
 
+pub fn emit_changes(changes: &ChangeList) {
+    for change in &changes.music_files {
+        let source_tag = &change.source.tag;
+        let target_tag = &change.target.tag;
+        console::get().emit(&ConsoleEvent::ChangePlanned {
+            kind: ChangeKind::MusicFile,
+            source: Some(change.source.file_path.display().to_string()),
+            target: change.target.file_path.display().to_string(),
+            frames: target_tag
+                .frame_ids()
+                .iter()
+                .map(|frame_id| FrameDiff {
+                    frame: frame_id.to_string(),
+                    source: source_tag.frame_content(frame_id).map(|v| v.to_string()),
+                    target: target_tag.frame_content(frame_id).map(|v| v.to_string()),
+                })
+                .filter(|diff| diff.source != diff.target)
+                .collect_vec(),
+        });
+    }
+
+    for change in &changes.covers {
+        console::get().emit(&ConsoleEvent::ChangePlanned {
+            kind: ChangeKind::Cover,
+            source: Some(change.source.to_string()),
+            target: change.path.display().to_string(),
+            frames: vec![],
+        });
+    }
+
+    for cleanup in &changes.cleanups {
+        console::get().emit(&ConsoleEvent::ChangePlanned {
+            kind: ChangeKind::Cleanup,
+            source: None,
+            target: cleanup.path.display().to_string(),
+            frames: vec![],
+        });
+    }
+}
+
 fn get_file_changes<'a>(
     discogs_match_results: &'a [DiscogsReleaseMatchResult],
-    output_path: &Option<PathBuf>,
+    output_paths: &OutputPaths,
+    path_options: &MusicPathOptions,
+    tag_options: &CreateTagOptions,
 ) -> Result<Vec<MusicFileChange<'a>>> {
     let mut result = Vec::new();
 
@@ -267,32 +488,82 @@ fn get_file_changes<'a>(
         .flat_map(|discogs_match_result| match discogs_match_result {
             Matched {
                 tracks_matching,
+                unmatched_music_files,
                 release,
             } => tracks_matching
                 .iter()
                 .map(|v| (v.music_file, Some((&v.track, release))))
+                .chain(unmatched_music_files.iter().map(|v| (*v, None)))
                 .collect_vec(),
-            Unmatched(music_files) => music_files.iter().map(|v| (v.deref(), None)).collect_vec(),
+            Unmatched(music_files) => music_files.iter().map(|v| (*v, None)).collect_vec(),
         })
         .collect_vec();
 
+    // Hand-tagged albums often carry wrong totals, so count files per folder and disc instead
+    let recounted_total_tracks = if tag_options.recount_tracks && tag_options.with_totals {
+        discogs_match_results
+            .iter()
+            .filter_map(|discogs_match_result| match discogs_match_result {
+                Unmatched(music_files) => Some(music_files),
+                Matched { .. } => None,
+            })
+            .flatten()
+            .map(|v| (v.file_path.parent_or_empty(), v.tag.disc()))
+            .counts()
+    } else {
+        HashMap::new()
+    };
+
     for (music_file, discogs_info) in match_items {
         let source_tag = &music_file.tag;
-        let target_tag = if let Some((discogs_track, discogs_release)) = discogs_info {
-            create_tag_from_discogs_data(source_tag, discogs_track, discogs_release)?
+        let mut target_tag = if let Some((discogs_track, discogs_release)) = discogs_info {
+            create_tag_from_discogs_data(source_tag, discogs_track, discogs_release, tag_options)?
+        } else if tag_options.keep_unmatched_tags {
+            let mut tag = source_tag.clone();
+            if let Some(genre) = &tag_options.fixed_genre {
+                tag.set_genre(Some(genre.to_owned()));
+            }
+            tag
         } else {
-            strip_redundant_fields(source_tag)?
+            strip_redundant_fields(source_tag, tag_options)?
         };
+        // Matched files keep Discogs totals even when unmatched extras share their folder
+        let recounted_total = recounted_total_tracks
+            .get(&(music_file.file_path.parent_or_empty(), source_tag.disc()))
+            .filter(|_| discogs_info.is_none());
+        if let Some(total_tracks) = recounted_total {
+            target_tag.set_total_tracks(Some(*total_tracks as u32));
+        }
+        for frame_id in &tag_options.strip_frames {
+            target_tag.set_frame(frame_id, None)?;
+        }
         let source_path = &music_file.file_path;
+        if tag_options.remember_original_name
+            && target_tag.custom_text(ORIGINAL_FILENAME_TAG).is_none()
+        {
+            target_tag.set_custom_text(
+                ORIGINAL_FILENAME_TAG.to_owned(),
+                Some(source_path.file_name_or_empty().to_owned()),
+            );
+        }
         let source_extension = source_path.extension_or_empty();
         let target_extension = source_extension;
         let source_file_length = fs::metadata(source_path)?.len();
-        let file_path = if let Some(output_path) = output_path {
-            output_path.join(relative_path_for(target_tag.deref(), target_extension)?)
+        let file_path = if let Some(output_path) = output_paths.output_path_for(source_path) {
+            output_path.join(relative_path_for(
+                target_tag.deref(),
+                target_extension,
+                is_single(discogs_info.map(|v| v.1)),
+                discogs_info.and_then(|v| v.1.label.as_deref()),
+                &output_paths.source_relative_path_for(source_path),
+                path_options,
+            )?)
         } else {
-            source_path
-                .parent_or_empty()
-                .join(music_file_name_for(target_tag.deref(), target_extension)?)
+            source_path.parent_or_empty().join(music_file_name_for(
+                target_tag.deref(),
+                target_extension,
+                path_options.output_case,
+            )?)
         };
         let duration = music_file.duration;
         let discogs_release = discogs_info.map(|v| v.1);
@@ -329,25 +600,167 @@ fn get_file_changes<'a>(
     Ok(result)
 }
 
-fn get_cover_changes(music_files: &Vec<MusicFileChange>) -> Result<Vec<CoverChange>> {
-    let mut cover_changes = HashSet::new();
+// A track resolved to another release than its folder mates ends up scattered into another folder
+fn warn_about_divergent_albums(music_files: &[MusicFileChange]) {
+    let folders = music_files
+        .iter()
+        .into_group_map_by(|v| v.source.file_path.parent_or_empty());
+    for (folder_path, music_files) in folders.into_iter().sorted_by_key(|v| v.0) {
+        // Folders mixing albums on purpose were already reported when splitting them
+        let source_album_count = music_files
+            .iter()
+            .filter_map(|v| v.source.tag.album())
+            .unique()
+            .count();
+        if source_album_count > 1 {
+            continue;
+        }
+        let albums = music_files
+            .iter()
+            .map(|v| {
+                let tag = &v.target.tag;
+                match tag.year() {
+                    Some(year) => format!("({}) {}", year, tag.album().unwrap_or_default()),
+                    None => tag.album().unwrap_or_default().to_owned(),
+                }
+            })
+            .unique()
+            .collect_vec();
+        if albums.len() > 1 {
+            console_warn!(
+                "{} {} ({})",
+                "Tracks of one folder resolved to different albums:".warning_styled(),
+                folder_path.display().path_styled(),
+                albums.join(", ").tag_styled()
+            );
+        }
+    }
+}
+
+fn is_single(discogs_release: Option<&DiscogsRelease>) -> bool {
+    discogs_release.is_some_and(|v| v.kind != DiscogsReleaseKind::Album)
+}
+
+fn get_cover_changes(
+    music_files: &Vec<MusicFileChange>,
+    path_options: &MusicPathOptions,
+) -> Result<Vec<CoverChange>> {
+    let mut cover_changes = Vec::new();
 
     for music_file in music_files {
         let Some(discogs_release) = music_file.discogs_release else { continue };
-        let Some(best_image) = &discogs_release.image else { continue };
-        let uri = best_image.url.to_owned();
-        let uri_as_file_path = PathBuf::from(Url::parse(&uri)?.path());
-        let extension = uri_as_file_path.extension_or_empty();
-        let path = music_file
-            .target
-            .file_path
-            .parent_or_empty()
-            .join(PathBuf::from(COVER_FILE_NAME_WITHOUT_EXTENSION).with_extension(extension));
-
-        cover_changes.insert(CoverChange { path, uri });
+        let existing_cover_path = if path_options.prefer_existing_cover {
+            find_existing_cover(music_file.source.file_path.parent_or_empty())
+        } else {
+            None
+        };
+        let (source, extension) = if let Some(existing_cover_path) = existing_cover_path {
+            let extension = existing_cover_path.extension_or_empty().to_lowercase();
+            (
+                CoverSource::Copy {
+                    path: existing_cover_path,
+                },
+                extension,
+            )
+        } else {
+            let Some(best_image) = &discogs_release.image else { continue };
+            let uri = best_image.url.to_owned();
+            let uri_as_file_path = PathBuf::from(Url::parse(&uri)?.path());
+            let extension = uri_as_file_path.extension_or_empty().to_owned();
+            (CoverSource::Download { uri }, extension)
+        };
+        let mut folder_path = music_file.target.file_path.parent_or_empty();
+        // Cover belongs to the whole album, not to the disc subfolder
+        if disc_subfolder_for(music_file.target.tag.deref(), path_options)
+            .is_some_and(|v| folder_path.ends_with(v))
+        {
+            folder_path = folder_path.parent_or_empty();
+        }
+        let extension = cover_extension_for(&extension, path_options);
+        let path = folder_path
+            .join(PathBuf::from(COVER_FILE_NAME_WITHOUT_EXTENSION).with_extension(&extension));
+        // Other players look for names like "folder.jpg" so the cover is duplicated under them
+        let sidecar_paths = path_options
+            .cover_sidecar_names
+            .iter()
+            .map(|name| {
+                let name = Path::new(name.trim()).file_stem().unwrap_or_default();
+                folder_path.join(PathBuf::from(name).with_extension(&extension))
+            })
+            .filter(|sidecar_path| sidecar_path != &path)
+            .collect_vec();
+
+        cover_changes.push(CoverChange {
+            path,
+            source,
+            sidecar_paths,
+            picture_type: PictureType::Front,
+        });
+
+        if path_options.all_covers {
+            for (index, image) in discogs_release.extra_images.iter().enumerate() {
+                let uri = image.url.to_owned();
+                let extension = cover_extension_for(
+                    PathBuf::from(Url::parse(&uri)?.path()).extension_or_empty(),
+                    path_options,
+                );
+                // Discogs doesn't label scans, the one following the front is usually the back
+                let (picture_type, name) = if index == 0 {
+                    (
+                        PictureType::Back,
+                        BACK_COVER_FILE_NAME_WITHOUT_EXTENSION.to_owned(),
+                    )
+                } else {
+                    (
+                        PictureType::Booklet,
+                        format!("{}-{:02}", BOOKLET_FILE_NAME_PREFIX, index),
+                    )
+                };
+                cover_changes.push(CoverChange {
+                    path: folder_path.join(PathBuf::from(name).with_extension(&extension)),
+                    source: CoverSource::Download { uri },
+                    sidecar_paths: vec![],
+                    picture_type,
+                });
+            }
+        }
+    }
+
+    Ok(cover_changes.into_iter().unique().collect_vec())
+}
+
+// Covers limited in size are always stored as JPEG so cleanup and sidecars know the final name
+fn cover_extension_for(extension: &str, path_options: &MusicPathOptions) -> String {
+    if path_options.cover_max_bytes.is_some() {
+        image_compression::jpeg_extension_for(extension).to_owned()
+    } else {
+        extension.to_owned()
     }
+}
 
-    Ok(cover_changes.into_iter().collect_vec())
+// Picks artwork the user already put next to the music, in order of the name preference
+fn find_existing_cover(folder_path: &Path) -> Option<PathBuf> {
+    let image_paths = folder_path
+        .read_dir()
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            EXISTING_COVER_EXTENSIONS.contains(&path.extension_or_empty().to_lowercase().as_str())
+        })
+        .sorted()
+        .collect_vec();
+    EXISTING_COVER_NAMES.iter().find_map(|name| {
+        image_paths
+            .iter()
+            .find(|path| {
+                path.file_stem()
+                    .and_then(|v| v.to_str())
+                    .is_some_and(|v| v.eq_ignore_ascii_case(name))
+            })
+            .cloned()
+    })
 }
 
 fn get_cleanup_changes(
@@ -355,6 +768,7 @@ fn get_cleanup_changes(
     covers: &Vec<CoverChange>,
     clean_source_folders: bool,
     clean_target_folders: bool,
+    no_source_cleanup_paths: &[PathBuf],
 ) -> Result<Vec<Cleanup>> {
     if !(clean_source_folders || clean_target_folders) {
         return Ok(vec![]);
@@ -362,12 +776,15 @@ fn get_cleanup_changes(
 
     let mut result = Vec::new();
 
-    let mut source_folder_paths = HashSet::new();
-    let mut target_folder_paths = HashSet::new();
+    // Ordered sets so that cleanups come out in the same order between runs
+    let mut source_folder_paths = BTreeSet::new();
+    let mut target_folder_paths = BTreeSet::new();
     let mut target_paths = HashSet::new();
 
     for change in music_files {
-        source_folder_paths.insert(change.source.file_path.parent_or_empty());
+        if !no_source_cleanup_paths.contains(&change.source.file_path) {
+            source_folder_paths.insert(change.source.file_path.parent_or_empty());
+        }
         target_folder_paths.insert(change.target.file_path.parent_or_empty());
         target_paths.insert(&change.target.file_path);
     }
@@ -375,6 +792,7 @@ fn get_cleanup_changes(
     for change in covers {
         target_folder_paths.insert(change.path.parent_or_empty());
         target_paths.insert(&change.path);
+        target_paths.extend(&change.sidecar_paths);
     }
 
     if clean_target_folders {
@@ -384,8 +802,10 @@ fn get_cleanup_changes(
                 .into_iter()
                 .flatten()
                 .filter_map(Result::ok)
-                .for_each(|entry| {
-                    let path = entry.path();
+                .map(|entry| entry.path())
+                .filter(|path| path.file_name_or_empty() != LOCK_FILE_NAME)
+                .sorted()
+                .for_each(|path| {
                     if !target_paths.contains(&path) {
                         result.push(Cleanup { path });
                     }
@@ -400,8 +820,10 @@ fn get_cleanup_changes(
                 .into_iter()
                 .flatten()
                 .filter_map(Result::ok)
-                .for_each(|entry| {
-                    let path = entry.path();
+                .map(|entry| entry.path())
+                .filter(|path| path.file_name_or_empty() != LOCK_FILE_NAME)
+                .sorted()
+                .for_each(|path| {
                     if !target_paths.contains(&path) {
                         result.push(Cleanup { path });
                     }
@@ -413,3 +835,49 @@ fn get_cleanup_changes(
 }
 
 const COVER_FILE_NAME_WITHOUT_EXTENSION: &str = "cover";
+const BACK_COVER_FILE_NAME_WITHOUT_EXTENSION: &str = "back";
+const BOOKLET_FILE_NAME_PREFIX: &str = "booklet";
+const EXISTING_COVER_NAMES: [&str; 3] = ["cover", "folder", "front"];
+const EXISTING_COVER_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn frames(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn kv_line_keeps_colons_in_value() {
+        let edited = "Title: Intro: Part 1\nAlbum:Live: 1999\nTXXX:CATALOG: A:1: B\n---\n";
+        let edited = edited.replace("---", KV_TRACK_DELIMITER);
+        assert_eq!(
+            parse_kv_editor_output(&edited).unwrap(),
+            vec![frames(&[
+                ("Title", "Intro: Part 1"),
+                ("Album", "Live: 1999"),
+                ("TXXX:CATALOG", "A:1: B"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn kv_prompt_round_trips_colons() {
+        let tracks_frames = vec![
+            frames(&[("Title", "Ratio: 3:2"), ("Album Artist", "A: B")]),
+            frames(&[("Title", "Time: 12:00"), ("TXXX:CATALOG", "A:1")]),
+        ];
+        let prompt = kv_editor_prompt(&tracks_frames).unwrap();
+        assert_eq!(parse_kv_editor_output(&prompt).unwrap(), tracks_frames);
+    }
+
+    #[test]
+    fn kv_line_without_colon_is_rejected() {
+        assert!(parse_kv_editor_output("Title").is_err());
+    }
+}
@@ -1,26 +1,72 @@
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::Seek;
+use std::io::{Read, Seek, Write};
+use std::mem;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{fs, io};
 
-use anyhow::{bail, Result};
-use dialoguer::Confirm;
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use clap::ValueEnum;
+use dialoguer::{Confirm, Input};
+use fs2::FileExt;
+use indicatif::HumanBytes;
 use itertools::Itertools;
 use progress_streams::{ProgressReader, ProgressWriter};
+use rayon::prelude::*;
+use regex::Regex;
+use tempfile::NamedTempFile;
 use walkdir::WalkDir;
 
 use crate::core::changes::{
-    calculate_changes, edit_changes, print_changes_details, Cleanup, CoverChange, MusicFileChange,
+    calculate_changes, edit_changes, emit_changes, print_changes_details, Cleanup, CoverChange,
+    CoverSource, MusicFileChange,
 };
-use crate::discogs::matcher::DiscogsMatcher;
-use crate::music_file::MusicFile;
+use crate::discogs::create_tag::{
+    read_genre_map, CreateTagOptions, FeatHandling, TagCase, DEFAULT_GENRE_SEPARATOR,
+};
+use crate::discogs::matcher::DiscogsReleaseMatchResult::{Matched, Unmatched};
+use crate::discogs::matcher::{
+    DiscogsMatcher, DiscogsMatcherOptions, DiscogsReleaseMatchResult, MixedAlbumFolders,
+    DEFAULT_DURATION_TOLERANCE, DEFAULT_MAX_EXTRA_FILES, DEFAULT_MIN_TRACK_COUNT,
+};
+use crate::music_file::{
+    inbox_path_for, ArtistFolderFrom, MusicFile, MusicPathOptions, OutputCase, OutputStructure,
+};
+use crate::tag;
+use crate::tag::{Picture, PictureType, Tag, ID3V1_TEXT_MAX_LEN};
 use crate::util::console;
+use crate::util::console_event::ConsoleEvent;
 use crate::util::console_styleable::ConsoleStyleable;
+use crate::util::image_compression;
+use crate::util::lyrics::LyricsIndex;
 use crate::util::path_extensions::PathExtensions;
-use crate::{pb_finish_with_message, pb_set_message};
+use crate::util::string_extensions::SimilarityOptions;
+use crate::{console_print, console_warn, pb_finish_with_message, pb_set_message};
 
 mod changes;
 
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EditorFormat {
+    Kv,
+    Toml,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PostVerifyMode {
+    Warn,
+    Strict,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConflictStrategy {
+    Skip,
+    Overwrite,
+    Rename,
+}
+
 #[derive(PartialEq)]
 pub enum AllowedChangeType {
     MusicFiles,
@@ -30,41 +76,525 @@ pub enum AllowedChangeType {
 }
 
 pub struct Args {
-    pub input_paths: Vec<PathBuf>,
-    pub output_path: Option<PathBuf>,
+    pub input_output_paths: Vec<(PathBuf, Option<PathBuf>)>,
     pub allowed_change_types: Vec<AllowedChangeType>,
+    // Files picked one by one, their source folders hold other things and are never cleaned
+    pub no_source_cleanup_paths: Vec<PathBuf>,
     pub allow_questions: bool,
     pub chunk_size: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub use_discogs: bool,
     pub discogs_token: Option<String>,
+    pub force: bool,
+    pub on_conflict: ConflictStrategy,
+    pub editor_format: EditorFormat,
+    pub concurrency_safe: bool,
+    pub matching: MatchingArgs,
+    pub tagging: TaggingArgs,
+    pub layout: LayoutArgs,
+    pub covers: CoverArgs,
+    pub cleanup: CleanupArgs,
+}
+
+pub struct MatchingArgs {
     pub discogs_release_id: Option<String>,
+    pub catalog_number_pattern: Option<String>,
+    pub duration_tolerance: Duration,
+    pub max_extra_files: usize,
+    pub similarity: SimilarityOptions,
+    pub tracks: Option<String>,
+    pub folder_tags_pattern: Option<String>,
+    pub infer_track_from_filename: bool,
+    pub year_from_folder: bool,
+    pub require_year: bool,
+    pub print_release_json: bool,
+    pub review_grouping: bool,
+    pub detect_swapped_tags: bool,
+    pub min_track_count: usize,
+    pub resume_from: Option<PathBuf>,
+    pub prefer_country: Option<String>,
+    pub assign_by_filename_order: bool,
+    pub mixed_album_folders: MixedAlbumFolders,
+    pub discogs_format_filter: Option<String>,
+    pub dump_search_queries: bool,
+    pub report_unmatched_path: Option<PathBuf>,
+}
+
+impl Default for MatchingArgs {
+    fn default() -> Self {
+        MatchingArgs {
+            discogs_release_id: None,
+            catalog_number_pattern: None,
+            duration_tolerance: DEFAULT_DURATION_TOLERANCE,
+            max_extra_files: DEFAULT_MAX_EXTRA_FILES,
+            similarity: SimilarityOptions::default(),
+            tracks: None,
+            folder_tags_pattern: None,
+            infer_track_from_filename: false,
+            year_from_folder: false,
+            require_year: false,
+            print_release_json: false,
+            review_grouping: false,
+            detect_swapped_tags: false,
+            min_track_count: DEFAULT_MIN_TRACK_COUNT,
+            resume_from: None,
+            prefer_country: None,
+            assign_by_filename_order: false,
+            mixed_album_folders: MixedAlbumFolders::Keep,
+            discogs_format_filter: None,
+            dump_search_queries: false,
+            report_unmatched_path: None,
+        }
+    }
+}
+
+pub struct TaggingArgs {
+    pub genre_map_path: Option<PathBuf>,
+    pub fixed_genre: Option<String>,
+    pub genre_separator: String,
+    pub prefer_local_frames: Vec<String>,
+    pub no_total_tracks: bool,
+    pub keep_unmatched_tags: bool,
+    pub remember_original_name: bool,
+    pub feat_handling: FeatHandling,
+    pub include_remixers: bool,
+    pub tag_case: TagCase,
+    pub tag_case_unmatched: bool,
+    pub recount_tracks: bool,
+    pub strip_frames: Vec<String>,
+    pub preserve_frames: Vec<String>,
+    pub preserve_all_custom: bool,
+    pub embed_lyrics_from: Option<PathBuf>,
+    pub write_id3v1: bool,
+    pub post_verify: Option<PostVerifyMode>,
+}
+
+impl Default for TaggingArgs {
+    fn default() -> Self {
+        TaggingArgs {
+            genre_map_path: None,
+            fixed_genre: None,
+            genre_separator: DEFAULT_GENRE_SEPARATOR.to_owned(),
+            prefer_local_frames: Vec::new(),
+            no_total_tracks: false,
+            keep_unmatched_tags: false,
+            remember_original_name: false,
+            feat_handling: FeatHandling::Keep,
+            include_remixers: false,
+            tag_case: TagCase::Keep,
+            tag_case_unmatched: false,
+            recount_tracks: false,
+            strip_frames: Vec::new(),
+            preserve_frames: Vec::new(),
+            preserve_all_custom: false,
+            embed_lyrics_from: None,
+            write_id3v1: false,
+            post_verify: None,
+        }
+    }
+}
+
+pub struct LayoutArgs {
+    pub output_structure: OutputStructure,
+    pub output_case: OutputCase,
+    pub default_album_artist: Option<String>,
+    pub artist_folder_from: ArtistFolderFrom,
+    pub label_fallback_to_artist: bool,
+    pub alpha_buckets: bool,
+    pub inbox_template: Option<String>,
+    pub singles_template: Option<String>,
+    pub disc_subfolder_template: Option<String>,
+}
+
+impl Default for LayoutArgs {
+    fn default() -> Self {
+        LayoutArgs {
+            output_structure: OutputStructure::ByArtist,
+            output_case: OutputCase::Preserve,
+            default_album_artist: None,
+            artist_folder_from: ArtistFolderFrom::AlbumArtist,
+            label_fallback_to_artist: false,
+            alpha_buckets: false,
+            inbox_template: None,
+            singles_template: None,
+            disc_subfolder_template: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CoverArgs {
+    pub max_bytes: Option<u64>,
+    pub write_file: bool,
+    pub embed: bool,
+    pub any_type: bool,
+    pub all_covers: bool,
+    pub sidecar_names: Vec<String>,
+    pub prefer_existing: bool,
+}
+
+#[derive(Default)]
+pub struct CleanupArgs {
+    pub backup_existing: bool,
+    pub dump_unmatched_path: Option<PathBuf>,
+    pub remove_empty_dirs: Option<bool>,
+    pub trash: bool,
+}
+
+pub const BACKUP_FOLDER_PREFIX: &str = ".backup-";
+pub const LOCK_FILE_NAME: &str = ".music-files-organizer.lock";
+const CONTENT_COMPARISON_CHUNK_SIZE: usize = 64 * 1024;
+
+struct CoverOptions {
+    max_bytes: Option<u64>,
+    write_file: bool,
+    embed: bool,
+    disc_subfolder_regex: Option<Regex>,
+}
+
+pub struct OutputPaths(Vec<(PathBuf, Option<PathBuf>)>);
+
+impl OutputPaths {
+    pub fn output_path_for(&self, source_path: &Path) -> Option<&PathBuf> {
+        self.0
+            .iter()
+            .filter(|(input_path, _)| source_path.starts_with(input_path))
+            .max_by_key(|(input_path, _)| input_path.components().count())
+            .and_then(|(_, output_path)| output_path.as_ref())
+    }
+
+    // Path below the input it was found in, files given directly keep their folder name
+    pub fn source_relative_path_for(&self, source_path: &Path) -> PathBuf {
+        self.0
+            .iter()
+            .filter(|(input_path, _)| source_path.starts_with(input_path))
+            .max_by_key(|(input_path, _)| input_path.components().count())
+            .and_then(|(input_path, _)| source_path.strip_prefix(input_path).ok())
+            .filter(|v| !v.as_os_str().is_empty())
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| {
+                Path::new(source_path.parent_or_empty().file_name_or_empty())
+                    .join(source_path.file_name_or_empty())
+            })
+    }
 }
 
 pub fn work(args: Args) -> Result<()> {
-    let discogs_matcher = DiscogsMatcher::with_optional_token(&args.discogs_token)?;
+    // Locks are held until the files are dropped on return
+    let _output_locks = if args.concurrency_safe {
+        lock_output_paths(&args.input_output_paths)?
+    } else {
+        Vec::new()
+    };
+
+    let discogs_matcher = if args.use_discogs {
+        Some(DiscogsMatcher::with_optional_token(
+            &args.discogs_token,
+            DiscogsMatcherOptions {
+                catalog_number_regex: args
+                    .matching
+                    .catalog_number_pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()?,
+                duration_tolerance: args.matching.duration_tolerance,
+                max_extra_files: args.matching.max_extra_files,
+                similarity: args.matching.similarity,
+                folder_tags_pattern: args.matching.folder_tags_pattern,
+                print_release_json: args.matching.print_release_json,
+                review_grouping: args.matching.review_grouping,
+                detect_swapped_tags: args.matching.detect_swapped_tags,
+                min_track_count: args.matching.min_track_count,
+                resume_from: args
+                    .matching
+                    .resume_from
+                    .as_deref()
+                    .map(|path| resolve_resume_from(path, &args.input_output_paths))
+                    .transpose()?,
+                prefer_country: args.matching.prefer_country,
+                assign_by_filename_order: args.matching.assign_by_filename_order,
+                mixed_album_folders: args.matching.mixed_album_folders,
+                format_filter: args.matching.discogs_format_filter,
+                dump_search_queries: args.matching.dump_search_queries,
+                allow_questions: args.allow_questions,
+            },
+        )?)
+    } else {
+        None
+    };
+
+    if let Some(discogs_matcher) = &discogs_matcher {
+        console_print!(
+            "Authenticated on Discogs as {}",
+            discogs_matcher.check_token()?.tag_styled()
+        );
+    }
 
-    match &args.output_path {
-        Some(output_path) => {
+    for (_, output_path) in &args.input_output_paths {
+        if let Some(output_path) = output_path {
             if !fs::metadata(output_path)?.is_dir() {
                 bail!("Output path is not a directory")
             }
         }
-        None => {}
     }
 
-    let music_files_chunks = get_music_files_chunks(args.input_paths, args.chunk_size);
+    let output_paths = OutputPaths(args.input_output_paths.clone());
+
+    // Resolved once so that a run spanning midnight lands in a single inbox folder
+    let import_time = Local::now();
+    let import_date = import_time.date_naive();
+    let backup_folder_name = format!(
+        "{}{}",
+        BACKUP_FOLDER_PREFIX,
+        import_time.format("%Y%m%d-%H%M%S")
+    );
+    let path_options = MusicPathOptions {
+        default_album_artist: args.layout.default_album_artist,
+        inbox_path: args
+            .layout
+            .inbox_template
+            .as_deref()
+            .map(|template| inbox_path_for(template, import_date)),
+        singles_template: args.layout.singles_template,
+        output_structure: args.layout.output_structure,
+        label_fallback_to_artist: args.layout.label_fallback_to_artist,
+        disc_subfolder_template: args.layout.disc_subfolder_template,
+        cover_sidecar_names: args.covers.sidecar_names,
+        prefer_existing_cover: args.covers.prefer_existing,
+        output_case: args.layout.output_case,
+        all_covers: args.covers.all_covers,
+        artist_folder_from: args.layout.artist_folder_from,
+        alpha_buckets: args.layout.alpha_buckets,
+        cover_max_bytes: args.covers.max_bytes,
+    };
+
+    let tag_options = CreateTagOptions {
+        genre_map: args
+            .tagging
+            .genre_map_path
+            .as_deref()
+            .map(read_genre_map)
+            .transpose()?
+            .unwrap_or_default(),
+        prefer_local_frames: args
+            .tagging
+            .prefer_local_frames
+            .iter()
+            .map(|frame| frame.trim().parse())
+            .try_collect()?,
+        with_totals: !args.tagging.no_total_tracks,
+        keep_unmatched_tags: args.tagging.keep_unmatched_tags,
+        fixed_genre: args.tagging.fixed_genre,
+        remember_original_name: args.tagging.remember_original_name,
+        genre_separator: args.tagging.genre_separator.as_str().into(),
+        feat_handling: args.tagging.feat_handling,
+        tag_case: args.tagging.tag_case,
+        tag_case_unmatched: args.tagging.tag_case_unmatched,
+        recount_tracks: args.tagging.recount_tracks,
+        strip_frames: args
+            .tagging
+            .strip_frames
+            .iter()
+            .map(|frame| frame.trim().parse())
+            .try_collect()?,
+        preserve_frames: args
+            .tagging
+            .preserve_frames
+            .iter()
+            .map(|frame| frame.trim().parse())
+            .try_collect()?,
+        preserve_all_custom: args.tagging.preserve_all_custom,
+        include_remixers: args.tagging.include_remixers,
+    };
+
+    let cover_options = CoverOptions {
+        max_bytes: args.covers.max_bytes,
+        write_file: args.covers.write_file,
+        embed: args.covers.embed,
+        disc_subfolder_regex: path_options
+            .disc_subfolder_template
+            .as_deref()
+            .map(disc_subfolder_regex_for)
+            .transpose()?,
+    };
+
+    let lyrics_index = args
+        .tagging
+        .embed_lyrics_from
+        .as_deref()
+        .map(LyricsIndex::from_dir)
+        .transpose()?;
+
+    let track_ranges = args
+        .matching
+        .tracks
+        .as_deref()
+        .map(parse_track_ranges)
+        .transpose()?;
+
+    let music_files_chunks = get_music_files_chunks(
+        args.input_output_paths
+            .into_iter()
+            .map(|(input_path, _)| input_path)
+            .collect_vec(),
+        args.chunk_size,
+        args.max_depth,
+        args.matching.infer_track_from_filename,
+        args.matching.year_from_folder,
+    );
 
     for music_files in music_files_chunks {
         let music_files = music_files?;
-        let discogs_releases =
-            discogs_matcher.match_music_files(music_files.iter(), &args.discogs_release_id)?;
+        let mut discogs_releases = match &discogs_matcher {
+            Some(discogs_matcher) => discogs_matcher
+                .match_music_files(music_files.iter(), &args.matching.discogs_release_id)?,
+            None => vec![Unmatched(music_files.iter().collect_vec())],
+        };
+
+        if args.matching.year_from_folder {
+            for discogs_release in &mut discogs_releases {
+                let Matched {
+                    release,
+                    tracks_matching,
+                    ..
+                } = discogs_release
+                else {
+                    continue;
+                };
+                if release.year.is_none() {
+                    release.year = tracks_matching
+                        .iter()
+                        .find_map(|v| v.music_file.tag.year());
+                }
+            }
+        }
+
+        if args.matching.require_year && args.allow_questions {
+            for discogs_release in &mut discogs_releases {
+                let Matched { release, .. } = discogs_release else {
+                    continue;
+                };
+                if release.year.is_none() {
+                    release.year = Some(
+                        Input::new()
+                            .with_prompt(format!("Please enter year for {}", release.title))
+                            .interact_text()?,
+                    );
+                }
+            }
+        }
+
+        for discogs_release in &mut discogs_releases {
+            let Matched { release, .. } = discogs_release else {
+                continue;
+            };
+            let Some(image) = &release.image else {
+                continue;
+            };
+            if image.is_cover_kind() {
+                continue;
+            }
+            if args.covers.any_type {
+                console_warn!(
+                    "{} {} ({})",
+                    "Using non-cover image, quality may vary:".warning_styled(),
+                    release.title.as_str().tag_styled(),
+                    image.kind
+                );
+            } else {
+                release.image = None;
+            }
+        }
+
+        if let Some(report_unmatched_path) = &args.matching.report_unmatched_path {
+            report_unmatched_folders(&discogs_releases, report_unmatched_path)?;
+        }
+
+        let mut unmatched_music_file_paths = Vec::new();
+        if args.cleanup.dump_unmatched_path.is_some() {
+            // Folder split into several groups may be matched in part, its sources must stay put
+            let matched_folder_paths: HashSet<PathBuf> = discogs_releases
+                .iter()
+                .filter_map(|discogs_release| match discogs_release {
+                    Matched {
+                        tracks_matching,
+                        unmatched_music_files,
+                        ..
+                    } => Some(
+                        tracks_matching
+                            .iter()
+                            .map(|v| v.music_file)
+                            .chain(unmatched_music_files.iter().copied()),
+                    ),
+                    Unmatched(_) => None,
+                })
+                .flatten()
+                .map(|v| v.file_path.parent_or_empty().to_owned())
+                .collect();
+            discogs_releases.retain(|discogs_release| {
+                let Unmatched(music_files) = discogs_release else {
+                    return true;
+                };
+                if let Some(music_file) = music_files
+                    .iter()
+                    .find(|v| matched_folder_paths.contains(v.file_path.parent_or_empty()))
+                {
+                    console_warn!(
+                        "{} {}",
+                        "Not dumping partially matched folder".warning_styled(),
+                        music_file
+                            .file_path
+                            .parent_or_empty()
+                            .display()
+                            .path_styled()
+                    );
+                    return true;
+                }
+                unmatched_music_file_paths
+                    .extend(music_files.iter().map(|v| v.file_path.to_owned()));
+                false
+            });
+        }
+
+        let mut skipped_paths = HashSet::new();
+        if let Some(track_ranges) = &track_ranges {
+            for discogs_release in &mut discogs_releases {
+                let Matched {
+                    tracks_matching, ..
+                } = discogs_release
+                else {
+                    continue;
+                };
+                let (selected, skipped): (Vec<_>, Vec<_>) =
+                    mem::take(tracks_matching).into_iter().partition(|v| {
+                        track_ranges
+                            .iter()
+                            .any(|range| range.contains(&v.track.position))
+                    });
+                *tracks_matching = selected;
+                skipped_paths.extend(skipped.into_iter().map(|v| v.music_file.file_path.clone()));
+            }
+        }
 
         let mut changes = calculate_changes(
             &discogs_releases,
-            &args.output_path,
+            &output_paths,
+            &path_options,
+            &tag_options,
             &args.allowed_change_types,
+            &args.no_source_cleanup_paths,
+            args.force,
         )?;
 
-        if changes.music_files.is_empty() && changes.covers.is_empty() && changes.covers.is_empty()
+        // Files left out by track selection must stay untouched
+        changes
+            .cleanups
+            .retain(|cleanup| !skipped_paths.contains(&cleanup.path));
+
+        if changes.music_files.is_empty()
+            && changes.covers.is_empty()
+            && changes.covers.is_empty()
+            && unmatched_music_file_paths.is_empty()
         {
             continue;
         }
@@ -87,7 +617,7 @@ pub fn work(args: Args) -> Result<()> {
                         .wait_for_newline(true)
                         .interact()?
                     {
-                        changes = edit_changes(changes, &args.output_path)?;
+                        changes = edit_changes(changes, &output_paths, &path_options, args.editor_format)?;
                     } else {
                         break;
                     }
@@ -97,6 +627,8 @@ pub fn work(args: Args) -> Result<()> {
             }
         }
 
+        emit_changes(&changes);
+
         if !args.allow_questions
             || Confirm::new()
                 .with_prompt("Do you want to make changes?")
@@ -105,24 +637,144 @@ pub fn work(args: Args) -> Result<()> {
                 .wait_for_newline(true)
                 .interact()?
         {
-            write_music_files(&changes.music_files)?;
-            download_covers(&discogs_matcher, &changes.covers)?;
-            cleanup(&changes.cleanups)?;
+            let backed_up_paths = if args.cleanup.backup_existing {
+                backup_existing_folders(&changes.music_files, &output_paths, &backup_folder_name)?
+            } else {
+                vec![]
+            };
+            let WrittenMusicFiles {
+                written_paths,
+                skipped_source_paths,
+            } = write_music_files(
+                &changes.music_files,
+                args.on_conflict,
+                lyrics_index.as_ref(),
+                args.tagging.post_verify,
+                args.tagging.write_id3v1,
+                // Embedded covers change files after writing, so they never equal freshly tagged ones
+                !cover_options.embed,
+            )?;
+            if let Some(discogs_matcher) = &discogs_matcher {
+                download_covers(
+                    discogs_matcher,
+                    &changes.covers,
+                    &written_paths,
+                    &cover_options,
+                )?;
+            }
+            if let Some(dump_unmatched_path) = &args.cleanup.dump_unmatched_path {
+                dump_unmatched_folders(&unmatched_music_file_paths, dump_unmatched_path)?;
+            }
+            cleanup(
+                &cleanups_to_perform(
+                    changes.cleanups,
+                    &written_paths,
+                    &skipped_source_paths,
+                    &backed_up_paths,
+                ),
+                args.cleanup.remove_empty_dirs,
+                args.cleanup.trash,
+            )?;
         }
     }
 
+    if discogs_matcher
+        .as_ref()
+        .is_some_and(DiscogsMatcher::is_resume_pending)
+    {
+        bail!("Resume folder was never reached, all folders were skipped");
+    }
+
     Ok(())
 }
 
+// Fails early on typos or paths outside the inputs, otherwise every folder would be skipped
+fn resolve_resume_from(
+    path: &Path,
+    input_output_paths: &[(PathBuf, Option<PathBuf>)],
+) -> Result<PathBuf> {
+    let path = fs::canonicalize(path)
+        .with_context(|| format!("Can't resolve folder to resume from: {}", path.display()))?;
+    if !path.is_dir() {
+        bail!("Resume point is not a folder: {}", path.display());
+    }
+    let is_inside_input = input_output_paths
+        .iter()
+        .filter_map(|(input_path, _)| fs::canonicalize(input_path).ok())
+        .any(|input_path| path.starts_with(input_path));
+    if !is_inside_input {
+        bail!("Resume folder is not inside any input: {}", path.display());
+    }
+    Ok(path)
+}
+
+fn parse_track_ranges(string: &str) -> Result<Vec<RangeInclusive<u32>>> {
+    string
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|part| -> Result<_> {
+            let context = || format!("Invalid track range: {}", part);
+            Ok(match part.split_once('-') {
+                Some((start, end)) => {
+                    start.trim().parse::<u32>().with_context(context)?
+                        ..=end.trim().parse::<u32>().with_context(context)?
+                }
+                None => {
+                    let position = part.parse::<u32>().with_context(context)?;
+                    position..=position
+                }
+            })
+        })
+        .collect()
+}
+
+// Advisory only: guards against another instance of this tool, not against other programs
+fn lock_output_paths(input_output_paths: &[(PathBuf, Option<PathBuf>)]) -> Result<Vec<File>> {
+    input_output_paths
+        .iter()
+        // Inputs may be single files, outputs are always folders even if not created yet
+        .filter_map(|(input_path, output_path)| {
+            output_path
+                .as_ref()
+                .or(Some(input_path).filter(|path| path.is_dir()))
+        })
+        .unique()
+        .map(|path| -> Result<File> {
+            fs::create_dir_all(path)
+                .with_context(|| format!("Can't create output folder {}", path.display()))?;
+            let lock_path = path.join(LOCK_FILE_NAME);
+            let file = File::options()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&lock_path)
+                .with_context(|| format!("Can't create lock file {}", lock_path.display()))?;
+            match file.try_lock_exclusive() {
+                Ok(()) => Ok(file),
+                Err(e) if e.kind() == fs2::lock_contended_error().kind() => bail!(
+                    "Another instance is already working with {}",
+                    path.display()
+                ),
+                Err(e) => Err(e).with_context(|| format!("Can't lock {}", lock_path.display())),
+            }
+        })
+        .try_collect()
+}
+
 fn get_music_files_chunks(
     input_paths: Vec<PathBuf>,
     chunk_size: Option<usize>,
+    max_depth: Option<usize>,
+    infer_track_from_filename: bool,
+    year_from_folder: bool,
 ) -> impl Iterator<Item = Result<Vec<MusicFile>>> {
     input_paths
         .iter()
         .map(|path| -> Result<_> {
             Ok(if fs::metadata(path)?.is_dir() {
                 WalkDir::new(path)
+                    .max_depth(max_depth.unwrap_or(usize::MAX))
                     .into_iter()
                     .filter_ok(|e| e.file_type().is_dir())
                     .collect_vec()
@@ -139,9 +791,8 @@ fn get_music_files_chunks(
         .map(|chunk| chunk.collect_vec())
         .collect_vec()
         .into_iter()
-        .map(|chunk| {
-            let pb = console::get_mut().new_default_spinner();
-            let result = chunk
+        .map(move |chunk| {
+            let files = chunk
                 .into_iter()
                 .flat_map(|e| {
                     WalkDir::new(e.path())
@@ -150,20 +801,201 @@ fn get_music_files_chunks(
                         .filter_map(Result::ok)
                 })
                 .filter(|e| !e.file_type().is_dir())
+                .collect_vec();
+            let pb = console::get_mut().new_default_counting_progress_bar(files.len() as u64);
+            let result = files
+                .into_par_iter()
                 .map(|file| {
                     pb_set_message!(pb, "Analyzing {}", file.path().display().path_styled());
-                    MusicFile::from_path(file.path())
+                    console::get().emit(&ConsoleEvent::Analyzing {
+                        path: file.path().display().to_string(),
+                    });
+                    let music_file = MusicFile::from_path(
+                        file.path(),
+                        infer_track_from_filename,
+                        year_from_folder,
+                    );
+                    pb.inc(1);
+                    music_file
                 })
+                .collect::<Vec<_>>()
+                .into_iter()
                 .flatten_ok()
-                .try_collect::<MusicFile, Vec<MusicFile>, _>();
+                .try_collect::<MusicFile, Vec<MusicFile>, _>()
+                .map(|music_files| {
+                    // Directory listing order is filesystem-dependent, keep runs reproducible
+                    music_files
+                        .into_iter()
+                        .sorted_by(|a, b| a.file_path.cmp(&b.file_path))
+                        .collect_vec()
+                });
             pb.finish_and_clear();
             result
         })
 }
 
-fn write_music_files(changes: &Vec<MusicFileChange>) -> Result<()> {
+// Moves existing target folders aside so the whole previous state could be restored
+fn backup_existing_folders(
+    changes: &[MusicFileChange],
+    output_paths: &OutputPaths,
+    backup_folder_name: &str,
+) -> Result<Vec<PathBuf>> {
+    let mut backed_up_paths = Vec::new();
+
+    for change in changes {
+        let Some(output_path) = output_paths.output_path_for(&change.source.file_path) else {
+            continue;
+        };
+        let target_folder_path = change.target.file_path.parent_or_empty();
+        if backed_up_paths.iter().any(|v| v == target_folder_path)
+            || !target_folder_path.is_dir()
+            // Never move away folders we are reading from
+            || changes
+                .iter()
+                .any(|v| v.source.file_path.starts_with(target_folder_path))
+        {
+            continue;
+        }
+        let Ok(relative_path) = target_folder_path.strip_prefix(output_path) else {
+            continue;
+        };
+        let backup_path = output_path.join(backup_folder_name).join(relative_path);
+        fs::create_dir_all(backup_path.parent_or_empty())?;
+        fs::rename(target_folder_path, &backup_path)?;
+        console_print!(
+            "Backed up {} to {}",
+            target_folder_path.display().path_styled(),
+            backup_path.display().path_styled()
+        );
+        backed_up_paths.push(target_folder_path.to_owned());
+    }
+
+    Ok(backed_up_paths)
+}
+
+// Subfolders are groups of their own which may be matched or wait for a later chunk, so only
+// unmatched files and sidecars next to them are moved
+fn dump_unmatched_folders(music_file_paths: &[PathBuf], dump_path: &Path) -> Result<()> {
+    fs::create_dir_all(dump_path)?;
+
+    let folders = music_file_paths
+        .iter()
+        .into_group_map_by(|v| v.parent_or_empty().to_owned());
+    for (folder_path, music_file_paths) in folders.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+        let mut target_path = dump_path.join(folder_path.file_name_or_empty());
+        if target_path.exists() {
+            target_path = free_path_for(&target_path);
+        }
+        move_folder_files(&folder_path, &music_file_paths, &target_path).with_context(|| {
+            format!(
+                "Failed to move unmatched {} to {}",
+                folder_path.display(),
+                target_path.display()
+            )
+        })?;
+        console_print!(
+            "Moved unmatched {} to {}",
+            folder_path.display().path_styled(),
+            target_path.display().path_styled()
+        );
+    }
+
+    Ok(())
+}
+
+// Other music files in the folder were given as separate inputs and are left alone
+fn move_folder_files(from: &Path, music_file_paths: &[&PathBuf], to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_sidecar =
+            !entry.file_type()?.is_dir() && !tag::is_supported_format(path.extension_or_empty());
+        if is_sidecar || music_file_paths.contains(&&path) {
+            move_file(&path, &to.join(entry.file_name()))?;
+        }
+    }
+    if from.read_dir()?.next().is_none() {
+        fs::remove_dir(from)?;
+    }
+    Ok(())
+}
+
+// Rename can't cross filesystems so the dump folder on another drive gets a copy instead
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)?;
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+// One line per folder with whatever artist and album the tags suggest, to triage by hand later
+fn report_unmatched_folders(
+    discogs_releases: &[DiscogsReleaseMatchResult],
+    report_path: &Path,
+) -> Result<()> {
+    let mut report = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(report_path)
+        .with_context(|| format!("Can't open unmatched report {}", report_path.display()))?;
+
+    for discogs_release in discogs_releases {
+        let Unmatched(music_files) = discogs_release else {
+            continue;
+        };
+        let folders = music_files
+            .iter()
+            .into_group_map_by(|v| v.file_path.parent_or_empty().to_owned());
+        for (folder_path, music_files) in folders.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+            let artist = music_files
+                .iter()
+                .filter_map(|v| v.tag.album_artist().or(v.tag.artist()))
+                .unique()
+                .join(" & ");
+            let album = music_files
+                .iter()
+                .filter_map(|v| v.tag.album())
+                .unique()
+                .join(", ");
+            writeln!(report, "{}\t{}\t{}", folder_path.display(), artist, album)?;
+        }
+    }
+
+    Ok(())
+}
+
+struct WrittenMusicFiles {
+    written_paths: Vec<PathBuf>,
+    // Sources whose existing targets were kept, they are the only copy of what was meant to be written
+    skipped_source_paths: Vec<PathBuf>,
+}
+
+fn write_music_files(
+    changes: &Vec<MusicFileChange>,
+    on_conflict: ConflictStrategy,
+    lyrics_index: Option<&LyricsIndex>,
+    post_verify: Option<PostVerifyMode>,
+    write_id3v1: bool,
+    skip_already_written: bool,
+) -> Result<WrittenMusicFiles> {
+    let mut written_paths = Vec::new();
+    let mut skipped_source_paths = Vec::new();
+    let mut unverified_paths = Vec::new();
+    let mut lyrics_embedded_count = 0;
+    let mut lyrics_missing_paths = Vec::new();
+    let mut already_written_count = 0;
+    let mut id3v1_truncated_paths = Vec::new();
+
     if changes.is_empty() {
-        return Ok(());
+        return Ok(WrittenMusicFiles {
+            written_paths,
+            skipped_source_paths,
+        });
     };
 
     let total_bytes_to_transfer: u64 = changes.iter().map(|v| v.source_file_length).sum();
@@ -174,8 +1006,20 @@ fn write_music_files(changes: &Vec<MusicFileChange>) -> Result<()> {
         let source = &change.source;
         let target = &change.target;
         let source_path = &source.file_path;
-        let target_path = &target.file_path;
-        let target_tag = &target.tag;
+        let mut target_path = &target.file_path;
+        let mut target_tag = &target.tag;
+
+        let target_exists = target_path != source_path && target_path.exists();
+        if target_exists && on_conflict == ConflictStrategy::Skip {
+            console_warn!(
+                "{} {}",
+                "Target exists, skipping".warning_styled(),
+                target_path.display().path_styled()
+            );
+            pb.inc(change.source_file_length);
+            skipped_source_paths.push(source_path.to_owned());
+            continue;
+        }
 
         pb_set_message!(
             pb,
@@ -183,7 +1027,31 @@ fn write_music_files(changes: &Vec<MusicFileChange>) -> Result<()> {
             source_path.file_name_or_empty().path_styled()
         );
 
-        fs::create_dir_all(target_path.parent_or_empty())?;
+        let tag_with_lyrics;
+        if let Some(lyrics_index) = lyrics_index {
+            match lyrics_index.find(&[source_path, &target.file_path], target_tag.title()) {
+                Some(lyrics_path) => match fs::read_to_string(lyrics_path) {
+                    Ok(lyrics) => {
+                        let mut tag = target_tag.clone();
+                        tag.set_lyrics(Some(lyrics));
+                        tag_with_lyrics = tag;
+                        target_tag = &tag_with_lyrics;
+                        lyrics_embedded_count += 1;
+                    }
+                    // One broken lyrics file shouldn't abort writing of the rest
+                    Err(error) => {
+                        console_warn!(
+                            "{} {} ({})",
+                            "Can't read lyrics".warning_styled(),
+                            lyrics_path.display().path_styled(),
+                            error
+                        );
+                        lyrics_missing_paths.push(target_path.to_owned());
+                    }
+                },
+                None => lyrics_missing_paths.push(target_path.to_owned()),
+            }
+        }
 
         let mut temp_file = {
             let mut source_file =
@@ -191,33 +1059,188 @@ fn write_music_files(changes: &Vec<MusicFileChange>) -> Result<()> {
             let mut temp_file = tempfile::tempfile()?;
             io::copy(&mut source_file, &mut temp_file)?;
             target_tag.write_to(&mut temp_file)?;
+            if write_id3v1 && target_tag.write_id3v1_to(&mut temp_file)? {
+                id3v1_truncated_paths.push(target_path.to_owned());
+            }
             temp_file
         };
 
+        // Target left by an interrupted earlier run, nothing to redo
+        if skip_already_written && target_exists && has_same_content(&mut temp_file, target_path)? {
+            if already_written_count == 0 {
+                console_print!("Resuming: skipping file(s) already written");
+            }
+            already_written_count += 1;
+            pb.inc(change.source_file_length - change.source_file_length / 2);
+            written_paths.push(target_path.to_owned());
+            continue;
+        }
+
+        let renamed_target_path;
+        if target_exists {
+            match on_conflict {
+                ConflictStrategy::Skip => {}
+                ConflictStrategy::Overwrite => {
+                    console_warn!(
+                        "{} {}",
+                        "Target exists, overwriting".warning_styled(),
+                        target_path.display().path_styled()
+                    );
+                }
+                ConflictStrategy::Rename => {
+                    renamed_target_path = free_path_for(target_path);
+                    console_warn!(
+                        "{} {} → {}",
+                        "Target exists, renaming".warning_styled(),
+                        target_path.display().path_styled(),
+                        renamed_target_path.display().path_styled()
+                    );
+                    target_path = &renamed_target_path;
+                }
+            }
+        }
+
+        fs::create_dir_all(target_path.parent_or_empty())?;
+
         temp_file.rewind()?;
 
         let source_file_len = change.source_file_length;
         let temp_file_len = temp_file.metadata()?.len();
-        let mut target_file = ProgressWriter::new(File::create(target_path)?, |bytes| {
-            pb.inc(bytes as u64 * source_file_len / temp_file_len / 2)
-        });
 
-        io::copy(&mut temp_file, &mut target_file)?;
+        // Stage next to the target so the final rename stays within one filesystem and is atomic
+        let mut target_file = NamedTempFile::new_in(target_path.parent_or_empty())?;
+        io::copy(
+            &mut temp_file,
+            &mut ProgressWriter::new(target_file.as_file_mut(), |bytes| {
+                pb.inc(bytes as u64 * source_file_len / temp_file_len / 2)
+            }),
+        )?;
+        fs::set_permissions(target_file.path(), fs::metadata(source_path)?.permissions())?;
+        target_file.persist(target_path)?;
+
+        if post_verify.is_some() {
+            let mismatched_frames = verify_written_file(target_path, target_tag)?;
+            if !mismatched_frames.is_empty() {
+                console_warn!(
+                    "{} {} ({})",
+                    "Written file differs from intended:".error_styled(),
+                    target_path.display().path_styled(),
+                    mismatched_frames.join(", ")
+                );
+                unverified_paths.push(target_path.to_owned());
+            }
+        }
+
+        console::get().emit(&ConsoleEvent::Written {
+            path: target_path.display().to_string(),
+        });
+        written_paths.push(target_path.to_owned());
     }
 
     pb_finish_with_message!(
         pb,
         "{}",
-        format!("Written {} file(s)", &changes.len())
+        format!("Written {} file(s)", written_paths.len())
             .styled()
             .green()
     );
 
-    Ok(())
+    if already_written_count > 0 {
+        console_print!("Skipped {} file(s) already written", already_written_count);
+    }
+
+    if !id3v1_truncated_paths.is_empty() {
+        console_warn!(
+            "{} {} file(s), ID3v1 keeps only {} characters of title, artist and album",
+            "Truncated legacy tag in".warning_styled(),
+            id3v1_truncated_paths.len(),
+            ID3V1_TEXT_MAX_LEN
+        );
+    }
+
+    // Bail out before cleanup has a chance to delete sources of broken files
+    if post_verify == Some(PostVerifyMode::Strict) && !unverified_paths.is_empty() {
+        bail!("Verification failed for {} written file(s)", unverified_paths.len());
+    }
+
+    if lyrics_index.is_some() {
+        console_print!("Embedded lyrics into {} file(s)", lyrics_embedded_count);
+        for path in lyrics_missing_paths {
+            console_warn!(
+                "{} {}",
+                "No lyrics found for".warning_styled(),
+                path.display().path_styled()
+            );
+        }
+    }
+
+    Ok(WrittenMusicFiles {
+        written_paths,
+        skipped_source_paths,
+    })
 }
 
-fn download_covers(discogs_matcher: &DiscogsMatcher, changes: &Vec<CoverChange>) -> Result<()> {
-    if changes.is_empty() {
+// Streams both files chunk by chunk so large lossless files never get loaded whole
+fn has_same_content(file: &mut File, path: &Path) -> Result<bool> {
+    let mut remaining = file.metadata()?.len();
+    if remaining != fs::metadata(path)?.len() {
+        return Ok(false);
+    }
+    file.rewind()?;
+    let mut other_file = File::open(path)?;
+    let mut chunk = vec![0u8; CONTENT_COMPARISON_CHUNK_SIZE];
+    let mut other_chunk = vec![0u8; CONTENT_COMPARISON_CHUNK_SIZE];
+    while remaining > 0 {
+        let size = remaining.min(CONTENT_COMPARISON_CHUNK_SIZE as u64) as usize;
+        file.read_exact(&mut chunk[..size])?;
+        other_file.read_exact(&mut other_chunk[..size])?;
+        if chunk[..size] != other_chunk[..size] {
+            return Ok(false);
+        }
+        remaining -= size as u64;
+    }
+    Ok(true)
+}
+
+#[allow(clippy::borrowed_box)]
+fn verify_written_file(path: &Path, expected_tag: &Box<dyn Tag>) -> Result<Vec<String>> {
+    let Some(written) = MusicFile::from_path(path, false, false)? else {
+        return Ok(vec!["unreadable tags".to_owned()]);
+    };
+    Ok(expected_tag
+        .frame_ids()
+        .iter()
+        .chain(written.tag.frame_ids().iter())
+        .filter(|frame_id| {
+            expected_tag.frame_content(frame_id) != written.tag.frame_content(frame_id)
+        })
+        .map(ToString::to_string)
+        .unique()
+        .collect_vec())
+}
+
+fn free_path_for(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension_or_empty();
+    (1..)
+        .map(|index| {
+            path.with_file_name(if extension.is_empty() {
+                format!("{} ({})", stem, index)
+            } else {
+                format!("{} ({}).{}", stem, index, extension)
+            })
+        })
+        .find(|path| !path.exists())
+        .unwrap_or_else(|| path.to_owned())
+}
+
+fn download_covers(
+    discogs_matcher: &DiscogsMatcher,
+    changes: &[CoverChange],
+    written_paths: &[PathBuf],
+    options: &CoverOptions,
+) -> Result<()> {
+    if changes.is_empty() || !(options.write_file || options.embed) {
         return Ok(());
     };
 
@@ -225,8 +1248,76 @@ fn download_covers(discogs_matcher: &DiscogsMatcher, changes: &Vec<CoverChange>)
     let pb = console::get_mut().new_default_progress_bar(!0);
 
     for (index, change) in changes.iter().enumerate() {
-        pb_set_message!(pb, "Downloading cover {}/{}", index + 1, count);
-        discogs_matcher.download_cover(&change.uri, &change.path, &pb)?;
+        let (mut data, etag) = match &change.source {
+            CoverSource::Download { uri } => {
+                pb_set_message!(pb, "Downloading cover {}/{}", index + 1, count);
+                let existing_path =
+                    Some(change.path.clone()).filter(|path| options.write_file && path.exists());
+                let Some(downloaded) =
+                    discogs_matcher.download_cover(uri, existing_path.as_deref(), &pb)?
+                else {
+                    let Some(path) = existing_path else { continue };
+                    console_print!("Cover is up to date {}", path.display().path_styled());
+                    let data = fs::read(&path)?;
+                    write_cover_sidecars(&path, &data, &change.sidecar_paths)?;
+                    if options.embed {
+                        embed_cover_into_album(
+                            &path,
+                            change.picture_type,
+                            &data,
+                            written_paths,
+                            options.disc_subfolder_regex.as_ref(),
+                        )?;
+                    }
+                    continue;
+                };
+                (downloaded.data, downloaded.etag)
+            }
+            CoverSource::Copy { path } => {
+                pb_set_message!(pb, "Copying cover {}/{}", index + 1, count);
+                (fs::read(path)?, None)
+            }
+        };
+        let path = &change.path;
+
+        if let Some(max_bytes) = options.max_bytes {
+            let original_size = data.len() as u64;
+            // Path was planned with JPEG extension so smaller images of other formats convert too
+            if original_size > max_bytes || !image_compression::is_jpeg(&data) {
+                pb_set_message!(pb, "Compressing cover {}/{}", index + 1, count);
+                data = image_compression::compress_to_fit(&data, max_bytes)?;
+                console_print!(
+                    "Compressed cover {} from {} to {}",
+                    path.display().path_styled(),
+                    HumanBytes(original_size),
+                    HumanBytes(data.len() as u64),
+                );
+            }
+        }
+
+        if options.write_file {
+            if fs::read(path).is_ok_and(|existing| existing == data) {
+                console_print!("Cover is unchanged {}", path.display().path_styled());
+            } else {
+                fs::write(path, &data)?;
+                console::get().emit(&ConsoleEvent::Written {
+                    path: path.display().to_string(),
+                });
+            }
+            // Copied covers have no ETag so a stale one from an earlier download is dropped
+            discogs_matcher.remember_cover_etag(path, &change.source.to_string(), etag);
+            write_cover_sidecars(path, &data, &change.sidecar_paths)?;
+        }
+
+        if options.embed {
+            embed_cover_into_album(
+                path,
+                change.picture_type,
+                &data,
+                written_paths,
+                options.disc_subfolder_regex.as_ref(),
+            )?;
+        }
     }
 
     pb_finish_with_message!(
@@ -238,11 +1329,141 @@ fn download_covers(discogs_matcher: &DiscogsMatcher, changes: &Vec<CoverChange>)
     Ok(())
 }
 
-fn cleanup(cleanups: &[Cleanup]) -> Result<()> {
+fn write_cover_sidecars(cover_path: &Path, data: &[u8], sidecar_paths: &[PathBuf]) -> Result<()> {
+    for sidecar_path in sidecar_paths {
+        if fs::read(sidecar_path).is_ok_and(|existing| existing == data) {
+            continue;
+        }
+        if sidecar_path.symlink_metadata().is_ok() {
+            fs::remove_file(sidecar_path)?;
+        }
+        link_cover_sidecar(cover_path, sidecar_path)?;
+        console::get().emit(&ConsoleEvent::Written {
+            path: sidecar_path.display().to_string(),
+        });
+    }
+    Ok(())
+}
+
+// Relative symlink keeps a single copy of the image and survives moving the album folder
+#[cfg(unix)]
+fn link_cover_sidecar(cover_path: &Path, sidecar_path: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(cover_path.file_name_or_empty(), sidecar_path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn link_cover_sidecar(cover_path: &Path, sidecar_path: &Path) -> Result<()> {
+    fs::copy(cover_path, sidecar_path)?;
+    Ok(())
+}
+
+fn embed_cover_into_album(
+    cover_path: &Path,
+    picture_type: PictureType,
+    data: &[u8],
+    written_paths: &[PathBuf],
+    disc_subfolder_regex: Option<&Regex>,
+) -> Result<()> {
+    let mime_type = match cover_path.extension_or_empty().to_lowercase().as_str() {
+        "png" => "image/png",
+        _ => "image/jpeg",
+    };
+    let album_folder_path = cover_path.parent_or_empty();
+    let album_paths = written_paths
+        .iter()
+        .filter(|v| is_in_album_folder(v, album_folder_path, disc_subfolder_regex))
+        .collect_vec();
+    let pb = console::get_mut().new_default_counting_progress_bar(album_paths.len() as u64);
+    for album_path in album_paths {
+        pb_set_message!(
+            pb,
+            "Embedding cover into {}",
+            album_path.file_name_or_empty().path_styled()
+        );
+        embed_cover(album_path, picture_type, mime_type, data)?;
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+    Ok(())
+}
+
+// Disc subfolders share the album cover, any other nested folder is an album of its own
+fn is_in_album_folder(
+    path: &Path,
+    album_folder_path: &Path,
+    disc_subfolder_regex: Option<&Regex>,
+) -> bool {
+    let folder_path = path.parent_or_empty();
+    folder_path == album_folder_path
+        || folder_path.parent_or_empty() == album_folder_path
+            && disc_subfolder_regex.is_some_and(|v| v.is_match(folder_path.file_name_or_empty()))
+}
+
+// Matches folder names the template yields for any disc, in any case the output may use
+fn disc_subfolder_regex_for(template: &str) -> Result<Regex> {
+    let pattern = regex::escape(template).replace(r"\{disc\}", r"\d+");
+    Ok(Regex::new(&format!("(?i)^{}$", pattern))?)
+}
+
+fn embed_cover(path: &Path, picture_type: PictureType, mime_type: &str, data: &[u8]) -> Result<()> {
+    let Some(mut tag) = tag::read_from_path(path, path.extension_or_empty())? else {
+        return Ok(());
+    };
+    if picture_type == PictureType::Front {
+        tag.set_front_cover(Some(Picture {
+            picture_type,
+            mime_type: mime_type.to_owned(),
+            data: data.to_owned(),
+        }));
+    } else if tag
+        .pictures()
+        .iter()
+        .any(|v| v.picture_type == picture_type && v.data == data)
+    {
+        // Pictures besides the front one pile up so the same scan is not added twice
+        return Ok(());
+    } else {
+        tag.add_picture(picture_type, mime_type.to_owned(), data.to_owned());
+    }
+    // Same as in write_music_files, an interrupted write must not leave a broken file behind
+    let mut target_file = NamedTempFile::new_in(path.parent_or_empty())?;
+    io::copy(&mut File::open(path)?, target_file.as_file_mut())?;
+    tag.write_to(target_file.as_file_mut())?;
+    fs::set_permissions(target_file.path(), fs::metadata(path)?.permissions())?;
+    target_file.persist(path)?;
+    Ok(())
+}
+
+// Keeps whatever was just written in place, backed up or still holds a skipped source
+fn cleanups_to_perform(
+    cleanups: Vec<Cleanup>,
+    written_paths: &[PathBuf],
+    skipped_source_paths: &[PathBuf],
+    backed_up_paths: &[PathBuf],
+) -> Vec<Cleanup> {
+    cleanups
+        .into_iter()
+        .filter(|cleanup| !written_paths.contains(&cleanup.path))
+        .filter(|cleanup| {
+            !skipped_source_paths
+                .iter()
+                .any(|path| path.starts_with(&cleanup.path))
+        })
+        .filter(|cleanup| {
+            !backed_up_paths
+                .iter()
+                .any(|path| cleanup.path.starts_with(path))
+        })
+        .collect_vec()
+}
+
+fn cleanup(cleanups: &[Cleanup], remove_empty_dirs: Option<bool>, trash: bool) -> Result<()> {
     for cleanup in cleanups {
         let path = &cleanup.path;
-        let metadata = fs::metadata(path)?;
-        if metadata.is_dir() {
+        if trash {
+            move_to_trash(path)?;
+        } else if fs::metadata(path)?.is_dir() {
             fs::remove_dir_all(path)?;
         } else {
             fs::remove_file(path)?;
@@ -255,17 +1476,28 @@ fn cleanup(cleanups: &[Cleanup]) -> Result<()> {
         while let Some(parent) = path.parent() {
             if Path::exists(parent)
                 && parent.read_dir()?.next().is_none()
-                && Confirm::new()
-                    .with_prompt(format!(
-                        "Directory {} is now empty. Do you wish to remove it?",
-                        parent.display().path_styled()
-                    ))
-                    .default(true)
-                    .show_default(true)
-                    .wait_for_newline(true)
-                    .interact()?
+                && match remove_empty_dirs {
+                    Some(remove) => remove,
+                    None => {
+                        let prompt = format!(
+                            "Directory {} is now empty. Do you wish to remove it?",
+                            parent.display().path_styled()
+                        );
+                        console::get().ensure_interactive(&prompt)?;
+                        Confirm::new()
+                            .with_prompt(prompt)
+                            .default(true)
+                            .show_default(true)
+                            .wait_for_newline(true)
+                            .interact()?
+                    }
+                }
             {
-                fs::remove_dir_all(parent)?;
+                if trash {
+                    move_to_trash(parent)?;
+                } else {
+                    fs::remove_dir_all(parent)?;
+                }
                 path = parent;
             } else {
                 break;
@@ -275,3 +1507,119 @@ fn cleanup(cleanups: &[Cleanup]) -> Result<()> {
 
     Ok(())
 }
+
+fn move_to_trash(path: &Path) -> Result<()> {
+    trash::delete(path).with_context(|| format!("Can't move {} to trash", path.display()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::music_file::DEFAULT_DISC_SUBFOLDER_TEMPLATE;
+
+    #[test]
+    fn source_relative_path_keeps_structure_below_input() {
+        let output_paths = OutputPaths(vec![(PathBuf::from("/music/inbox"), None)]);
+        assert_eq!(
+            output_paths.source_relative_path_for(Path::new("/music/inbox/Artist/Album/01.mp3")),
+            PathBuf::from("Artist/Album/01.mp3")
+        );
+    }
+
+    #[test]
+    fn source_relative_path_keeps_folder_of_file_given_directly() {
+        let output_paths = OutputPaths(vec![(PathBuf::from("/music/inbox/Album/01.mp3"), None)]);
+        assert_eq!(
+            output_paths.source_relative_path_for(Path::new("/music/inbox/Album/01.mp3")),
+            PathBuf::from("Album/01.mp3")
+        );
+    }
+
+    #[test]
+    fn skip_keeps_source() {
+        let cleanups = vec![
+            Cleanup {
+                path: PathBuf::from("/music/inbox/Album/01.mp3"),
+            },
+            Cleanup {
+                path: PathBuf::from("/music/inbox/Album/02.mp3"),
+            },
+            Cleanup {
+                path: PathBuf::from("/music/inbox/Album/CD2"),
+            },
+        ];
+        let skipped_source_paths = vec![
+            PathBuf::from("/music/inbox/Album/01.mp3"),
+            PathBuf::from("/music/inbox/Album/CD2/01.mp3"),
+        ];
+        assert_eq!(
+            cleanups_to_perform(cleanups, &[], &skipped_source_paths, &[])
+                .into_iter()
+                .map(|cleanup| cleanup.path)
+                .collect_vec(),
+            vec![PathBuf::from("/music/inbox/Album/02.mp3")]
+        );
+    }
+
+    #[test]
+    fn same_content_is_compared_across_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = (0..CONTENT_COMPARISON_CHUNK_SIZE * 2 + 10)
+            .map(|v| v as u8)
+            .collect_vec();
+        let path = dir.path().join("a.flac");
+        fs::write(&path, &data).unwrap();
+        let mut changed_data = data.clone();
+        changed_data[CONTENT_COMPARISON_CHUNK_SIZE + 1] ^= 1;
+        let changed_path = dir.path().join("b.flac");
+        fs::write(&changed_path, &changed_data).unwrap();
+        let shorter_path = dir.path().join("c.flac");
+        fs::write(&shorter_path, &data[1..]).unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        assert!(has_same_content(&mut file, &path).unwrap());
+        assert!(!has_same_content(&mut file, &changed_path).unwrap());
+        assert!(!has_same_content(&mut file, &shorter_path).unwrap());
+    }
+
+    #[test]
+    fn cover_is_embedded_into_album_and_disc_folders_only() {
+        let regex = disc_subfolder_regex_for(DEFAULT_DISC_SUBFOLDER_TEMPLATE).unwrap();
+        let album_path = Path::new("/music/Artist/(1997) Album");
+        let is_in_album =
+            |path: &str| is_in_album_folder(Path::new(path), album_path, Some(&regex));
+        assert!(is_in_album("/music/Artist/(1997) Album/01.mp3"));
+        assert!(is_in_album("/music/Artist/(1997) Album/CD2/01.mp3"));
+        assert!(is_in_album("/music/Artist/(1997) Album/cd2/01.mp3"));
+        assert!(!is_in_album("/music/Artist/(1997) Album/Bonus/01.mp3"));
+        assert!(!is_in_album("/music/Artist/(1997) Album/CD2/Extra/01.mp3"));
+        assert!(!is_in_album("/music/Artist/(1998) Other/01.mp3"));
+        assert!(!is_in_album_folder(
+            Path::new("/music/Artist/(1997) Album/CD2/01.mp3"),
+            album_path,
+            None
+        ));
+    }
+
+    #[test]
+    fn dump_unmatched_leaves_subfolders_and_other_music_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let album_path = temp_dir.path().join("inbox/Album");
+        fs::create_dir_all(album_path.join("CD2")).unwrap();
+        for name in ["01.mp3", "cover.jpg", "other.mp3", "CD2/01.mp3"] {
+            fs::write(album_path.join(name), []).unwrap();
+        }
+        let dump_path = temp_dir.path().join("dump");
+
+        dump_unmatched_folders(&[album_path.join("01.mp3")], &dump_path).unwrap();
+
+        assert!(dump_path.join("Album/01.mp3").exists());
+        assert!(dump_path.join("Album/cover.jpg").exists());
+        assert!(!dump_path.join("Album/other.mp3").exists());
+        assert!(!dump_path.join("Album/CD2").exists());
+        assert!(album_path.join("other.mp3").exists());
+        assert!(album_path.join("CD2/01.mp3").exists());
+        assert!(!album_path.join("01.mp3").exists());
+    }
+}
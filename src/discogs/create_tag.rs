@@ -1,23 +1,86 @@
 use std::borrow::ToOwned;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use std::string::ToString;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
+use regex::Regex;
 
 use crate::discogs::model::refined::{DiscogsRelease, DiscogsTrack};
 use crate::tag::frame::FrameId;
 use crate::tag::Tag;
+use crate::util::string_extensions::StringExtensions;
+
+const REMIX_ROLE: &str = "Remix";
+
+pub struct CreateTagOptions {
+    pub genre_map: HashMap<String, String>,
+    pub prefer_local_frames: Vec<FrameId>,
+    pub with_totals: bool,
+    pub keep_unmatched_tags: bool,
+    pub fixed_genre: Option<String>,
+    pub remember_original_name: bool,
+    pub genre_separator: GenreSeparator,
+    pub feat_handling: FeatHandling,
+    pub tag_case: TagCase,
+    pub tag_case_unmatched: bool,
+    pub recount_tracks: bool,
+    pub strip_frames: Vec<FrameId>,
+    pub preserve_frames: Vec<FrameId>,
+    pub preserve_all_custom: bool,
+    pub include_remixers: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FeatHandling {
+    Keep,
+    MoveToArtist,
+    MoveToTitle,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TagCase {
+    Keep,
+    Title,
+    Upper,
+    Lower,
+}
+
+pub enum GenreSeparator {
+    Join(String),
+    MultiValue,
+}
+
+pub const DEFAULT_GENRE_SEPARATOR: &str = "; ";
+pub const MULTI_VALUE_GENRE_SEPARATOR: &str = "multi";
+
+impl From<&str> for GenreSeparator {
+    fn from(value: &str) -> Self {
+        if value == MULTI_VALUE_GENRE_SEPARATOR {
+            GenreSeparator::MultiValue
+        } else {
+            GenreSeparator::Join(value.to_owned())
+        }
+    }
+}
 
 #[allow(clippy::borrowed_box)]
 pub fn create_tag_from_discogs_data(
     original_tag: &Box<dyn Tag>, // FIXME: Can't create new tag without "template" for now
     discogs_track: &DiscogsTrack,
     discogs_release: &DiscogsRelease,
+    options: &CreateTagOptions,
 ) -> Result<Box<dyn Tag>> {
     let mut new_tag = original_tag.clone();
     new_tag.clear();
 
+    // Carried over first so that frames set from Discogs below still take precedence
+    copy_preserved_frames(&mut new_tag, original_tag, options)?;
+
     new_tag.set_title(Some(discogs_track.title.to_owned()));
     new_tag.set_album(Some(discogs_release.title.to_owned()));
     let album_artists: Vec<(&str, &str)> = discogs_release
@@ -52,44 +115,290 @@ pub fn create_tag_from_discogs_data(
             .trim()
             .to_owned(),
     ));
-    new_tag.set_year(Some(discogs_release.year));
+    new_tag.set_year(discogs_release.year);
     new_tag.set_track_number(Some(discogs_track.position));
-    new_tag.set_total_tracks(Some(
-        discogs_release.disc_to_total_tracks[&discogs_track.disc],
-    ));
+    if options.with_totals {
+        new_tag.set_total_tracks(Some(
+            discogs_release.disc_to_total_tracks[&discogs_track.disc],
+        ));
+    }
     let total_discs = discogs_release.disc_to_total_tracks.keys().len() as u32;
     if total_discs > 1 {
         new_tag.set_disc(Some(discogs_track.disc));
-        new_tag.set_total_discs(Some(total_discs));
-    }
-    new_tag.set_genre(Some(
-        discogs_release
-            .styles
-            .as_deref()
-            .unwrap_or_default()
-            .join("; "),
-    ));
+        if options.with_totals {
+            new_tag.set_total_discs(Some(total_discs));
+        }
+    }
+    let discogs_genres = discogs_release
+        .styles
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(|style| options.genre_map.get(style).unwrap_or(style))
+        .unique()
+        .map(ToOwned::to_owned)
+        .collect_vec();
+    if let Some(genre) = &options.fixed_genre {
+        new_tag.set_genre(Some(genre.to_owned()));
+    } else if discogs_genres.is_empty() {
+        // Don't clobber existing genre when Discogs has no styles for the release
+        new_tag.set_genre(original_tag.genre().map(ToOwned::to_owned));
+    } else {
+        match &options.genre_separator {
+            GenreSeparator::Join(separator) => {
+                new_tag.set_genre(Some(discogs_genres.join(separator)))
+            }
+            GenreSeparator::MultiValue => new_tag.set_genres(discogs_genres),
+        }
+    }
+    new_tag.set_encoded_by(original_tag.encoded_by().map(ToOwned::to_owned));
+    new_tag.set_album_sort(original_tag.album_sort().map(ToOwned::to_owned));
+    new_tag.set_title_sort(original_tag.title_sort().map(ToOwned::to_owned));
+    new_tag.set_isrc(
+        discogs_track
+            .isrc
+            .to_owned()
+            .or_else(|| original_tag.isrc().map(ToOwned::to_owned)),
+    );
     new_tag.set_custom_text(
         DISCOGS_RELEASE_TAG.to_owned(),
         Some(discogs_release.uri.to_owned()),
     );
+    new_tag.set_custom_text(
+        DISCOGS_RELEASE_ID_TAG.to_owned(),
+        Some(discogs_release.id.to_string()),
+    );
+    new_tag.set_custom_text(
+        ORIGINAL_FILENAME_TAG.to_owned(),
+        original_tag
+            .custom_text(ORIGINAL_FILENAME_TAG)
+            .map(ToOwned::to_owned),
+    );
+    for key in MUSICBRAINZ_TAGS {
+        new_tag.copy_frame_from(
+            original_tag,
+            &FrameId::CustomText {
+                key: key.to_owned(),
+            },
+        )?;
+    }
+
+    if options.include_remixers {
+        apply_remixers(new_tag.as_mut(), discogs_track, discogs_release);
+    }
+    apply_feat_handling(new_tag.as_mut(), options.feat_handling);
+    apply_tag_case(new_tag.as_mut(), options.tag_case);
+
+    for frame_id in &options.prefer_local_frames {
+        if let Some(content) = original_tag.frame_content(frame_id) {
+            new_tag.set_frame(frame_id, Some(content))?;
+        }
+    }
 
     Ok(new_tag)
 }
 
+fn apply_feat_handling(tag: &mut dyn Tag, feat_handling: FeatHandling) {
+    let title = tag.title().unwrap_or_default().to_owned();
+    let artist = tag.artist().unwrap_or_default().to_owned();
+    // Credit is merged into destination first so it is never dropped from both fields
+    match feat_handling {
+        FeatHandling::Keep => {}
+        FeatHandling::MoveToArtist => {
+            let Some((title, featured)) = split_feat(&title) else {
+                return;
+            };
+            tag.set_artist(Some(merge_feat(&artist, &featured, |main, featured| {
+                format!("{} feat. {}", main, featured).trim().to_owned()
+            })));
+            tag.set_title(Some(title));
+        }
+        FeatHandling::MoveToTitle => {
+            let Some((artist, featured)) = split_feat(&artist) else {
+                return;
+            };
+            tag.set_title(Some(merge_feat(&title, &featured, |main, featured| {
+                format!("{} (feat. {})", main, featured).trim().to_owned()
+            })));
+            tag.set_artist(Some(artist));
+        }
+    }
+}
+
+// Adds featured artists to a value which may already credit some of them
+fn merge_feat(value: &str, featured: &str, format: impl Fn(&str, &str) -> String) -> String {
+    match split_feat(value) {
+        Some((_, existing)) if existing.to_lowercase().contains(&featured.to_lowercase()) => {
+            value.to_owned()
+        }
+        Some((main, existing)) => format(&main, &format!("{} & {}", existing, featured)),
+        None => format(value, featured),
+    }
+}
+
+// Appends "(Name Remix)" unless title already credits the remixer, as Discogs titles often do
+fn apply_remixers(
+    tag: &mut dyn Tag,
+    discogs_track: &DiscogsTrack,
+    discogs_release: &DiscogsRelease,
+) {
+    let title = tag.title().unwrap_or_default().to_owned();
+    let lowercase_title = title.to_lowercase();
+    // Release level credits limited to some tracks are skipped as positions are not matched yet
+    let remixers = discogs_track
+        .extra_artists
+        .iter()
+        .chain(
+            discogs_release
+                .extra_artists
+                .iter()
+                .filter(|v| v.tracks.is_none()),
+        )
+        .filter(|v| v.has_role(REMIX_ROLE))
+        .map(|v| v.name.as_str())
+        .filter(|v| !lowercase_title.contains(&v.to_lowercase()))
+        .unique()
+        .collect_vec();
+    if remixers.is_empty() {
+        return;
+    }
+    tag.set_title(Some(format!("{} ({} Remix)", title, remixers.join(" & "))));
+}
+
+fn apply_tag_case(tag: &mut dyn Tag, tag_case: TagCase) {
+    if tag_case == TagCase::Keep {
+        return;
+    }
+    let convert = |value: Option<&str>| {
+        value.map(|value| match tag_case {
+            TagCase::Keep => value.to_owned(),
+            TagCase::Title => value.to_title_case(),
+            TagCase::Upper => value.to_uppercase(),
+            TagCase::Lower => value.to_lowercase(),
+        })
+    };
+    let title = convert(tag.title());
+    let album = convert(tag.album());
+    let artist = convert(tag.artist());
+    let album_artist = convert(tag.album_artist());
+    tag.set_title(title);
+    tag.set_album(album);
+    tag.set_artist(artist);
+    tag.set_album_artist(album_artist);
+}
+
+#[allow(clippy::unwrap_used)]
+static FEAT_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\s*([(\[])?\b(?:featuring|feat|ft)\b\.?\s*").unwrap());
+
+// Splits "Main (feat. Other)" or "Main ft. Other - Remix" into main part and featured artists
+fn split_feat(value: &str) -> Option<(String, String)> {
+    let captures = FEAT_REGEX.captures(value)?;
+    let whole = captures.get(0)?;
+    let rest = &value[whole.end()..];
+    let (featured_end, rest_start) = if captures.get(1).is_some() {
+        match rest.find([')', ']']) {
+            Some(index) => (index, index + 1),
+            None => (rest.len(), rest.len()),
+        }
+    } else {
+        // Unbracketed credit runs until the next title part
+        let index = [" - ", "(", "["]
+            .iter()
+            .filter_map(|v| rest.find(v))
+            .min()
+            .unwrap_or(rest.len());
+        let index = rest[..index].trim_end().len();
+        (index, index)
+    };
+    let featured = rest[..featured_end].trim();
+    let main = format!("{}{}", &value[..whole.start()], &rest[rest_start..]);
+    Some((main.trim().to_owned(), featured.to_owned()))
+        .filter(|v| !v.0.is_empty() && !v.1.is_empty())
+}
+
+pub fn read_genre_map(path: &Path) -> Result<HashMap<String, String>> {
+    let mut result = HashMap::new();
+
+    for line in fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (from, to) = line
+            .split_once("=>")
+            .with_context(|| format!("Invalid genre mapping line: {}", line))?;
+        result.insert(from.trim().to_owned(), to.trim().to_owned());
+    }
+
+    Ok(result)
+}
+
 #[allow(clippy::borrowed_box)]
-pub fn strip_redundant_fields(tag: &Box<dyn Tag>) -> Result<Box<dyn Tag>> {
+pub fn strip_redundant_fields(
+    tag: &Box<dyn Tag>,
+    options: &CreateTagOptions,
+) -> Result<Box<dyn Tag>> {
     let mut new_tag = tag.clone();
     new_tag.clear();
 
     for frame_id in ALLOWED_FRAMES.iter() {
-        new_tag.set_frame(frame_id, tag.frame_content(frame_id))?;
+        new_tag.copy_frame_from(tag, frame_id)?;
+    }
+    copy_preserved_frames(&mut new_tag, tag, options)?;
+
+    if let Some(genre) = &options.fixed_genre {
+        new_tag.set_genre(Some(genre.to_owned()));
+    }
+
+    if options.tag_case_unmatched {
+        apply_tag_case(new_tag.as_mut(), options.tag_case);
     }
 
     Ok(new_tag)
 }
 
-const DISCOGS_RELEASE_TAG: &str = "DISCOGS_RELEASE";
+// Frames asked for with --preserve-frame and --preserve-all-custom
+#[allow(clippy::borrowed_box)]
+fn copy_preserved_frames(
+    new_tag: &mut Box<dyn Tag>,
+    original_tag: &Box<dyn Tag>,
+    options: &CreateTagOptions,
+) -> Result<()> {
+    for frame_id in &options.preserve_frames {
+        new_tag.copy_frame_from(original_tag, frame_id)?;
+    }
+
+    if options.preserve_all_custom {
+        for frame_id in original_tag.frame_ids() {
+            if let FrameId::CustomText { .. } = frame_id {
+                new_tag.copy_frame_from(original_tag, &frame_id)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub const DISCOGS_RELEASE_TAG: &str = "DISCOGS_RELEASE";
+pub const ORIGINAL_FILENAME_TAG: &str = "ORIGINAL_FILENAME";
+pub const DISCOGS_RELEASE_ID_TAG: &str = "DISCOGS_RELEASE_ID";
+// Picard writes Vorbis style keys into FLAC and descriptive ones into ID3 and MP4, except for
+// the ID3 track ID which lives in a UFID frame and is mapped onto the same key by the backend
+const MUSICBRAINZ_TAGS: [&str; 12] = [
+    "MUSICBRAINZ_ALBUMID",
+    "MUSICBRAINZ_ARTISTID",
+    "MUSICBRAINZ_ALBUMARTISTID",
+    "MUSICBRAINZ_RELEASEGROUPID",
+    "MUSICBRAINZ_RELEASETRACKID",
+    "MUSICBRAINZ_TRACKID",
+    "MusicBrainz Album Id",
+    "MusicBrainz Artist Id",
+    "MusicBrainz Album Artist Id",
+    "MusicBrainz Release Group Id",
+    "MusicBrainz Release Track Id",
+    "MusicBrainz Track Id",
+];
 static ALLOWED_FRAMES: Lazy<Vec<FrameId>> = Lazy::new(|| {
     vec![
         FrameId::Title,
@@ -102,8 +411,246 @@ static ALLOWED_FRAMES: Lazy<Vec<FrameId>> = Lazy::new(|| {
         FrameId::Disc,
         FrameId::TotalDiscs,
         FrameId::Genre,
+        FrameId::EncodedBy,
+        FrameId::Isrc,
+        FrameId::AlbumSort,
+        FrameId::TitleSort,
         FrameId::CustomText {
             key: DISCOGS_RELEASE_TAG.to_string(),
         },
+        FrameId::CustomText {
+            key: ORIGINAL_FILENAME_TAG.to_string(),
+        },
+        FrameId::CustomText {
+            key: DISCOGS_RELEASE_ID_TAG.to_string(),
+        },
     ]
+    .into_iter()
+    .chain(MUSICBRAINZ_TAGS.iter().map(|key| FrameId::CustomText {
+        key: key.to_string(),
+    }))
+    .collect()
 });
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use crate::discogs::model::refined::DiscogsReleaseKind;
+
+    use super::*;
+
+    fn tag_with(title: &str, artist: &str) -> Box<dyn Tag> {
+        let mut tag: Box<dyn Tag> = Box::new(::id3::Tag::new());
+        tag.set_title(Some(title.to_owned()));
+        tag.set_artist(Some(artist.to_owned()));
+        tag
+    }
+
+    fn discogs_track() -> DiscogsTrack {
+        DiscogsTrack {
+            title: "Song".to_owned(),
+            position: 1,
+            disc: 1,
+            duration: None,
+            artists: None,
+            extra_artists: vec![],
+            isrc: None,
+        }
+    }
+
+    fn discogs_release() -> DiscogsRelease {
+        DiscogsRelease {
+            id: 1,
+            uri: "https://www.discogs.com/release/1".to_owned(),
+            title: "Album".to_owned(),
+            year: Some(2000),
+            styles: None,
+            image: None,
+            extra_images: vec![],
+            tracks: vec![discogs_track()],
+            disc_to_total_tracks: HashMap::from([(1, 1)]),
+            artists: vec![],
+            extra_artists: vec![],
+            kind: DiscogsReleaseKind::Album,
+            label: Some("Label".to_owned()),
+            country: None,
+        }
+    }
+
+    fn options() -> CreateTagOptions {
+        CreateTagOptions {
+            genre_map: HashMap::new(),
+            prefer_local_frames: vec![],
+            with_totals: false,
+            keep_unmatched_tags: false,
+            fixed_genre: None,
+            remember_original_name: false,
+            genre_separator: GenreSeparator::Join(DEFAULT_GENRE_SEPARATOR.to_owned()),
+            feat_handling: FeatHandling::Keep,
+            tag_case: TagCase::Keep,
+            tag_case_unmatched: false,
+            recount_tracks: false,
+            strip_frames: vec![],
+            preserve_frames: vec![],
+            preserve_all_custom: false,
+            include_remixers: false,
+        }
+    }
+
+    fn custom(key: &str) -> FrameId {
+        FrameId::CustomText {
+            key: key.to_owned(),
+        }
+    }
+
+    #[test]
+    fn matched_tag_keeps_preserved_frames() {
+        let mut original_tag = tag_with("Local Title", "A");
+        original_tag.set_custom_text(
+            "REPLAYGAIN_TRACK_GAIN".to_owned(),
+            Some("-6.5 dB".to_owned()),
+        );
+        original_tag.set_custom_text("MOOD".to_owned(), Some("Calm".to_owned()));
+        let mut options = options();
+        options.preserve_frames = vec![custom("REPLAYGAIN_TRACK_GAIN"), FrameId::Title];
+
+        let tag = create_tag_from_discogs_data(
+            &original_tag,
+            &discogs_track(),
+            &discogs_release(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(tag.custom_text("REPLAYGAIN_TRACK_GAIN"), Some("-6.5 dB"));
+        assert_eq!(tag.custom_text("MOOD"), None);
+        // Discogs data still wins for the frames it sets
+        assert_eq!(tag.title(), Some("Song"));
+
+        options.preserve_all_custom = true;
+        let tag = create_tag_from_discogs_data(
+            &original_tag,
+            &discogs_track(),
+            &discogs_release(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(tag.custom_text("MOOD"), Some("Calm"));
+    }
+
+    #[test]
+    fn matched_tag_keeps_musicbrainz_ids() {
+        let mut original_tag = tag_with("Song", "A");
+        original_tag.set_custom_text(
+            "MusicBrainz Album Id".to_owned(),
+            Some("album-id".to_owned()),
+        );
+        original_tag.set_custom_text(
+            "MusicBrainz Track Id".to_owned(),
+            Some("track-id".to_owned()),
+        );
+
+        let tag = create_tag_from_discogs_data(
+            &original_tag,
+            &discogs_track(),
+            &discogs_release(),
+            &options(),
+        )
+        .unwrap();
+        assert_eq!(tag.custom_text("MusicBrainz Album Id"), Some("album-id"));
+        assert_eq!(tag.custom_text("MusicBrainz Track Id"), Some("track-id"));
+    }
+
+    #[test]
+    fn matched_tag_gets_no_label_frame() {
+        let tag = create_tag_from_discogs_data(
+            &tag_with("Song", "A"),
+            &discogs_track(),
+            &discogs_release(),
+            &options(),
+        )
+        .unwrap();
+        assert_eq!(tag.custom_text("LABEL"), None);
+    }
+
+    #[test]
+    fn matched_tag_keeps_sort_fields() {
+        let mut original_tag = tag_with("Song", "A");
+        original_tag.set_album_sort(Some("Album, The".to_owned()));
+        original_tag.set_title_sort(Some("Song, The".to_owned()));
+
+        let tag = create_tag_from_discogs_data(
+            &original_tag,
+            &discogs_track(),
+            &discogs_release(),
+            &options(),
+        )
+        .unwrap();
+        assert_eq!(tag.album_sort(), Some("Album, The"));
+        assert_eq!(tag.title_sort(), Some("Song, The"));
+    }
+
+    fn feat(main: &str, featured: &str) -> Option<(String, String)> {
+        Some((main.to_owned(), featured.to_owned()))
+    }
+
+    #[test]
+    fn split_feat_spellings() {
+        assert_eq!(split_feat("Song (feat. X)"), feat("Song", "X"));
+        assert_eq!(split_feat("Song (Feat X)"), feat("Song", "X"));
+        assert_eq!(split_feat("Song [ft. X]"), feat("Song", "X"));
+        assert_eq!(split_feat("Song ft X"), feat("Song", "X"));
+        assert_eq!(split_feat("Song featuring X & Y"), feat("Song", "X & Y"));
+        assert_eq!(split_feat("A FEAT. B"), feat("A", "B"));
+    }
+
+    #[test]
+    fn split_feat_stops_at_next_title_part() {
+        assert_eq!(split_feat("Song ft. X - Remix"), feat("Song - Remix", "X"));
+        assert_eq!(split_feat("Song feat. X (Live)"), feat("Song (Live)", "X"));
+        assert_eq!(
+            split_feat("Song (feat. X) [Live]"),
+            feat("Song [Live]", "X")
+        );
+    }
+
+    #[test]
+    fn split_feat_ignores_words_containing_feat() {
+        assert_eq!(split_feat("Feature Presentation"), None);
+        assert_eq!(split_feat("Daft Punk"), None);
+        assert_eq!(split_feat("feat. X"), None);
+    }
+
+    #[test]
+    fn move_to_artist() {
+        let mut tag = tag_with("Song (feat. X)", "A");
+        apply_feat_handling(tag.as_mut(), FeatHandling::MoveToArtist);
+        assert_eq!(tag.title(), Some("Song"));
+        assert_eq!(tag.artist(), Some("A feat. X"));
+    }
+
+    #[test]
+    fn move_to_artist_merges_with_existing_credit() {
+        let mut tag = tag_with("Song (feat. Y)", "A feat. X");
+        apply_feat_handling(tag.as_mut(), FeatHandling::MoveToArtist);
+        assert_eq!(tag.title(), Some("Song"));
+        assert_eq!(tag.artist(), Some("A feat. X & Y"));
+
+        let mut tag = tag_with("Song (feat. X)", "A ft. X");
+        apply_feat_handling(tag.as_mut(), FeatHandling::MoveToArtist);
+        assert_eq!(tag.title(), Some("Song"));
+        assert_eq!(tag.artist(), Some("A ft. X"));
+    }
+
+    #[test]
+    fn move_to_title() {
+        let mut tag = tag_with("Song - Remix", "A ft. X");
+        apply_feat_handling(tag.as_mut(), FeatHandling::MoveToTitle);
+        assert_eq!(tag.title(), Some("Song - Remix (feat. X)"));
+        assert_eq!(tag.artist(), Some("A"));
+
+        let mut tag = tag_with("Song (feat. X)", "A featuring Y");
+        apply_feat_handling(tag.as_mut(), FeatHandling::MoveToTitle);
+        assert_eq!(tag.title(), Some("Song (feat. X & Y)"));
+        assert_eq!(tag.artist(), Some("A"));
+    }
+}
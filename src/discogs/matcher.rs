@@ -1,41 +1,165 @@
 use std::borrow::Borrow;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
+use std::io::Write;
+use std::mem;
 use std::mem::swap;
-use std::ops::Deref;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use std::{f64, fs, thread};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::{env, f64, fs, thread};
 
 use anyhow::{bail, Context, Result};
-use dialoguer::{Input, Select};
+use clap::ValueEnum;
+use dialoguer::{Confirm, Input, MultiSelect, Select};
 use indicatif::ProgressBar;
 use itertools::Itertools;
+use once_cell::sync::Lazy;
 use progress_streams::ProgressWriter;
 use regex::Regex;
 use reqwest::blocking::Response;
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, USER_AGENT};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, ETAG, IF_NONE_MATCH, USER_AGENT};
 use reqwest::{blocking, IntoUrl, StatusCode, Url};
+use rayon::prelude::*;
 use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
 
 use DiscogsReleaseMatchResult::Matched;
 
-use crate::console_print;
+use crate::{console_print, console_print_verbose, console_warn, pb_set_message};
+use crate::discogs::create_tag::{DISCOGS_RELEASE_ID_TAG, DISCOGS_RELEASE_TAG};
 use crate::discogs::model::refined;
 use crate::discogs::model::serialized;
 use crate::music_file::MusicFile;
+use crate::util::console;
+use crate::util::console::{VERBOSITY_HTTP, VERBOSITY_MATCHING};
+use crate::util::console_event::ConsoleEvent;
 use crate::util::console_styleable::ConsoleStyleable;
 use crate::util::path_extensions::PathExtensions;
-use crate::util::string_extensions::StringExtensions;
+use crate::util::string_extensions::{SimilarityOptions, StringExtensions};
 
 pub struct DiscogsMatcher {
     http_client: blocking::Client,
+    options: DiscogsMatcherOptions,
+    simulated_rate_limit: Option<f64>,
+    simulated_rate_limit_debt: Mutex<f64>,
+    masters_cache: Mutex<HashMap<String, CachedMaster>>,
+    master_fetch_pool: rayon::ThreadPool,
+    cover_etags: Mutex<HashMap<PathBuf, CoverEtag>>,
+    resume_pending: AtomicBool,
 }
 
+pub struct DiscogsMatcherOptions {
+    pub catalog_number_regex: Option<Regex>,
+    pub duration_tolerance: Duration,
+    pub max_extra_files: usize,
+    pub similarity: SimilarityOptions,
+    pub folder_tags_pattern: Option<String>,
+    pub print_release_json: bool,
+    pub review_grouping: bool,
+    pub detect_swapped_tags: bool,
+    pub min_track_count: usize,
+    pub resume_from: Option<PathBuf>,
+    pub prefer_country: Option<String>,
+    pub assign_by_filename_order: bool,
+    pub mixed_album_folders: MixedAlbumFolders,
+    pub format_filter: Option<String>,
+    pub dump_search_queries: bool,
+    pub allow_questions: bool,
+}
+
+impl Default for DiscogsMatcherOptions {
+    fn default() -> Self {
+        DiscogsMatcherOptions {
+            catalog_number_regex: None,
+            duration_tolerance: DEFAULT_DURATION_TOLERANCE,
+            max_extra_files: DEFAULT_MAX_EXTRA_FILES,
+            similarity: SimilarityOptions::default(),
+            folder_tags_pattern: None,
+            print_release_json: false,
+            review_grouping: false,
+            detect_swapped_tags: false,
+            min_track_count: DEFAULT_MIN_TRACK_COUNT,
+            resume_from: None,
+            prefer_country: None,
+            assign_by_filename_order: false,
+            mixed_album_folders: MixedAlbumFolders::Keep,
+            format_filter: None,
+            dump_search_queries: false,
+            allow_questions: false,
+        }
+    }
+}
+
+impl Drop for DiscogsMatcher {
+    fn drop(&mut self) {
+        // Cache is an optimization only, failing to persist it is not worth reporting
+        let _ = self.save_masters_cache();
+        let _ = self.save_cover_etags();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MixedAlbumFolders {
+    Split,
+    Prompt,
+    Keep,
+}
+
+pub struct DownloadedCover {
+    pub data: Vec<u8>,
+    pub etag: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedMaster {
+    fetched_at: u64,
+    master: serialized::DiscogsMaster,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CoverEtag {
+    uri: String,
+    etag: String,
+}
+
+#[derive(Default)]
+struct FolderTags {
+    artist: Option<String>,
+    album: Option<String>,
+}
+
+#[allow(clippy::unwrap_used)]
+static DISC_SUFFIX_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\s*[(\[]?\b(?:cd|disc|disk)\s*\d+\s*[)\]]?\s*$").unwrap());
+
 const DISCOGS_TOKEN_FILE_NAME: &str = ".discogs_token";
+const MASTERS_CACHE_RELATIVE_PATH: &str = "music-files-organizer/discogs_masters.json";
+const COVER_ETAGS_RELATIVE_PATH: &str = "music-files-organizer/cover_etags.json";
+// Masters rarely change but may get a new main release, so refetch them once in a while
+const MASTERS_CACHE_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+// Masters are resolved in parallel, keep it low to not run into the rate limit right away
+const MAX_CONCURRENT_MASTER_FETCHES: usize = 2;
+// No more than 5 release fetches per params combinations to give other combinations realistic chances
+const MAX_RELEASE_FETCHES_PER_PARAMS: usize = 5;
+// How many matching releases to look through for the preferred country before taking the first one
+const MAX_COUNTRY_CANDIDATES: usize = 5;
+// Testing hook: fraction of responses (less than 1.0) to be treated as 429
+const SIMULATE_RATE_LIMIT_ENV: &str = "MFO_SIMULATE_RATE_LIMIT";
+const SIMULATED_RATE_LIMIT: f64 = 60f64;
+pub const DEFAULT_DURATION_TOLERANCE: Duration = Duration::from_secs(30);
+pub const DEFAULT_MAX_EXTRA_FILES: usize = 2;
+pub const DEFAULT_MIN_TRACK_COUNT: usize = 1;
+pub const DEFAULT_FOLDER_TAGS_PATTERN: &str = "{artist}/{album}";
+pub const DEFAULT_CATALOG_NUMBER_PATTERN: &str = r"\[([A-Za-z]+[ -]?\d+[A-Za-z]*)\]";
 
 impl DiscogsMatcher {
-    pub fn with_optional_token(discogs_token: &Option<String>) -> Result<Self> {
+    pub fn with_optional_token(
+        discogs_token: &Option<String>,
+        options: DiscogsMatcherOptions,
+    ) -> Result<Self> {
         let discogs_token = match discogs_token {
             Some(x) => x.to_owned(),
             None => {
@@ -45,14 +169,63 @@ impl DiscogsMatcher {
             }
         };
 
-        DiscogsMatcher::new(&discogs_token)
+        DiscogsMatcher::new(&discogs_token, options)
+    }
+
+    pub fn save_token(discogs_token: &str) -> Result<()> {
+        let discogs_token_file = Self::get_discogs_token_file_path()
+            .context("Can't resolve home folder to save Discogs token to")?;
+        if discogs_token_file.exists() {
+            console_warn!(
+                "{} {}",
+                "Overwriting existing token file".warning_styled(),
+                discogs_token_file.display().path_styled()
+            );
+        }
+
+        let mut open_options = fs::OpenOptions::new();
+        open_options.write(true).create(true).truncate(true);
+        // Token is a secret so keep it readable by the owner only
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            open_options.mode(0o600);
+            if discogs_token_file.exists() {
+                fs::set_permissions(&discogs_token_file, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        open_options
+            .open(&discogs_token_file)?
+            .write_all(discogs_token.as_bytes())?;
+
+        console_print!(
+            "Saved Discogs token to {}",
+            discogs_token_file.display().path_styled()
+        );
+        Ok(())
+    }
+
+    pub fn check_token(&self) -> Result<String> {
+        let identity: serialized::DiscogsIdentity = self
+            .fetch_by_url("https://api.discogs.com/oauth/identity")
+            .context("Discogs token is invalid or expired")?;
+        Ok(identity.username)
     }
 
-    pub fn new(discogs_token: &str) -> Result<Self> {
+    pub fn new(discogs_token: &str, options: DiscogsMatcherOptions) -> Result<Self> {
         Ok(DiscogsMatcher {
             http_client: blocking::ClientBuilder::new()
                 .default_headers(Self::common_headers(discogs_token)?)
                 .build()?,
+            simulated_rate_limit: Self::simulated_rate_limit()?,
+            simulated_rate_limit_debt: Mutex::new(0f64),
+            masters_cache: Mutex::new(Self::load_masters_cache()),
+            master_fetch_pool: rayon::ThreadPoolBuilder::new()
+                .num_threads(MAX_CONCURRENT_MASTER_FETCHES)
+                .build()?,
+            cover_etags: Mutex::new(Self::load_cover_etags()),
+            resume_pending: AtomicBool::new(options.resume_from.is_some()),
+            options,
         })
     }
 
@@ -84,9 +257,11 @@ pub struct DiscogsTrackMatch<'a> {
     pub track: refined::DiscogsTrack,
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum DiscogsReleaseMatchResult<'a> {
     Matched {
         tracks_matching: Vec<DiscogsTrackMatch<'a>>,
+        unmatched_music_files: Vec<&'a MusicFile>,
         release: refined::DiscogsRelease,
     },
     Unmatched(Vec<&'a MusicFile>),
@@ -107,18 +282,114 @@ impl DiscogsMatcher {
                 .push(music_file);
         }
 
+        // Stable order makes runs comparable and resuming meaningful
+        let mut groups = files_grouped_by_parent_path
+            .into_iter()
+            .sorted_by_key(|(path, _)| *path)
+            .collect_vec();
+        groups = self.split_mixed_albums(groups)?;
+        if self.options.review_grouping {
+            groups = Self::review_grouping(groups)?;
+        }
+
         let mut result = Vec::new();
+        let pb = console::get_mut().new_default_counting_progress_bar(groups.len() as u64);
+
+        for (path, music_files) in groups {
+            pb_set_message!(
+                pb,
+                "Matching {} – {}",
+                music_files
+                    .iter()
+                    .flat_map(|v| v.tag.artists())
+                    .unique()
+                    .join(" & ")
+                    .tag_styled(),
+                music_files
+                    .iter()
+                    .filter_map(|v| v.tag.album())
+                    .unique()
+                    .join(", ")
+                    .tag_styled(),
+            );
+            pb.inc(1);
+
+            if self.should_skip_until_resumed(path) {
+                console_warn!(
+                    "{} {}",
+                    "Skipping until resume folder is reached:".warning_styled(),
+                    path.display().path_styled()
+                );
+                continue;
+            }
+
+            if music_files.len() < self.options.min_track_count {
+                console_warn!(
+                    "{} {}",
+                    "Too few files to match, will use file tags as is:".warning_styled(),
+                    path.display().path_styled()
+                );
+                result.push(DiscogsReleaseMatchResult::Unmatched(music_files));
+                continue;
+            }
 
-        for (path, music_files) in files_grouped_by_parent_path {
             let mut match_result: DiscogsReleaseMatchResult =
                 DiscogsReleaseMatchResult::Unmatched(music_files.clone());
 
             if force_discogs_release_id.is_none() {
+                if let Some(release_id) = Self::release_id_from_tags(&music_files) {
+                    console_print!(
+                        "Found Discogs release {} in tags",
+                        release_id.as_str().tag_styled()
+                    );
+                    // Take year from master as search does, so that re-imports are stable
+                    let fetched = self.fetch_release_by_id(&release_id).and_then(|release| {
+                        let master = release
+                            .master_url
+                            .as_ref()
+                            .map(|url| self.fetch_master(url))
+                            .transpose()?;
+                        Ok((release, master))
+                    });
+                    // Release from tags is only a shortcut, search still has a chance on failure
+                    if let Err(error) = &fetched {
+                        console_warn!(
+                            "{} {} ({})",
+                            "Can't fetch release from tags, searching instead:".warning_styled(),
+                            release_id.as_str().tag_styled(),
+                            error
+                        );
+                    }
+                    if let Ok((serialized_release, master)) = fetched {
+                        let refined_release =
+                            refined::DiscogsRelease::from(&serialized_release, master)?;
+
+                        // FIXME: clone() is redundant here
+                        if let Some((tracks_matching, unmatched_music_files)) =
+                            self.match_release_with_music_files(
+                                refined_release.clone(),
+                                &music_files,
+                                true,
+                            )?
+                        {
+                            match_result = Matched {
+                                tracks_matching,
+                                unmatched_music_files,
+                                release: refined_release,
+                            };
+                        }
+                    }
+                }
+            }
+
+            if force_discogs_release_id.is_none()
+                && matches!(match_result, DiscogsReleaseMatchResult::Unmatched(_))
+            {
                 console_print!(
                     "Matching Discogs for {} – {}",
                     music_files
                         .iter()
-                        .filter_map(|v| v.tag.artist().map(ToString::to_string))
+                        .flat_map(|v| v.tag.artists())
                         .unique()
                         .join(" & ")
                         .tag_styled(),
@@ -130,15 +401,24 @@ impl DiscogsMatcher {
                         .tag_styled(),
                 );
 
-                let common_search_params =
-                    Self::common_search_params_from_music_files(&music_files);
+                let common_search_params = Self::common_search_params_from_music_files(
+                    &music_files,
+                    self.catalog_number_from_path(path),
+                    &self.folder_tags_from_path(path),
+                    &self.options.similarity,
+                    self.options.format_filter.as_deref(),
+                );
                 let release_infos = common_search_params.iter().flat_map(|params| {
                     self.search_master_release(params)
                         .chain(self.search_release(params))
-                        .take(5) // No more than 5 release fetches per params combinations to give other combinations realistic chances
+                        .take(MAX_RELEASE_FETCHES_PER_PARAMS)
                 });
 
                 let mut checked_release_urls = HashSet::new();
+                let mut fallback_match_result = None;
+                let mut matched_candidates_count = 0;
+                let mut mismatched_tracks_counts = Vec::new();
+                let mut filename_order_candidate = None;
                 for release_info in release_infos {
                     let (release_url, master) = release_info?;
                     if checked_release_urls.contains(&release_url) {
@@ -147,28 +427,98 @@ impl DiscogsMatcher {
                         checked_release_urls.insert(release_url.clone());
                     }
 
-                    let Ok(serialized_release) = self.fetch_by_url(release_url) else {
+                    let Ok(serialized_release) = self.fetch_release_by_url(release_url) else {
                         continue
                     };
 
                     let refined_release = refined::DiscogsRelease::from(&serialized_release, master)?;
 
+                    let tracks_count = refined_release.tracks.len();
+                    console_print_verbose!(
+                        VERBOSITY_MATCHING,
+                        "Trying {} ({} tracks for {} files)",
+                        refined_release.uri.as_str().path_styled(),
+                        tracks_count,
+                        music_files.len()
+                    );
+                    if !self.is_tracks_count_acceptable(tracks_count, music_files.len()) {
+                        console_print_verbose!(VERBOSITY_MATCHING, "Rejected: tracks count");
+                        mismatched_tracks_counts.push(tracks_count);
+                        continue;
+                    }
+
                     // FIXME: clone() is redundant here
-                    match Self::match_release_with_music_files(
-                        refined_release.clone(),
-                        &music_files,
-                        false,
-                    ) {
-                        None => continue,
-                        Some(tracks_matching) => {
-                            match_result = Matched {
-                                tracks_matching,
-                                release: refined_release,
-                            };
-                            break;
+                    let Some((tracks_matching, unmatched_music_files)) = self
+                        .match_release_with_music_files(
+                            refined_release.clone(),
+                            &music_files,
+                            false,
+                        )?
+                    else {
+                        if self.options.assign_by_filename_order
+                            && tracks_count == music_files.len()
+                        {
+                            filename_order_candidate.get_or_insert(refined_release);
                         }
+                        continue;
+                    };
+
+                    let Some(prefer_country) = &self.options.prefer_country else {
+                        match_result = Matched {
+                            tracks_matching,
+                            unmatched_music_files,
+                            release: refined_release,
+                        };
+                        break;
+                    };
+
+                    let is_preferred_country = refined_release
+                        .country
+                        .as_deref()
+                        .is_some_and(|v| v.eq_ignore_ascii_case(prefer_country));
+                    let candidate_match_result = Matched {
+                        tracks_matching,
+                        unmatched_music_files,
+                        release: refined_release,
+                    };
+                    if is_preferred_country {
+                        match_result = candidate_match_result;
+                        break;
+                    }
+
+                    // Keep the first match to fall back to when no pressing from the country matches
+                    fallback_match_result.get_or_insert(candidate_match_result);
+                    matched_candidates_count += 1;
+                    if matched_candidates_count >= MAX_COUNTRY_CANDIDATES {
+                        break;
                     }
                 }
+
+                if let (DiscogsReleaseMatchResult::Unmatched(_), Some(fallback_match_result)) =
+                    (&match_result, fallback_match_result)
+                {
+                    match_result = fallback_match_result;
+                }
+
+                if let (DiscogsReleaseMatchResult::Unmatched(_), Some(release)) =
+                    (&match_result, filename_order_candidate)
+                {
+                    if let Some(tracks_matching) =
+                        self.match_by_filename_order(&release, &music_files)?
+                    {
+                        match_result = Matched {
+                            tracks_matching,
+                            unmatched_music_files: vec![],
+                            release,
+                        };
+                    }
+                }
+
+                if matches!(match_result, DiscogsReleaseMatchResult::Unmatched(_))
+                    && !mismatched_tracks_counts.is_empty()
+                {
+                    Self::print_tracks_count_mismatch(music_files.len(), &mismatched_tracks_counts);
+                }
             }
 
             if !matches!(force_discogs_release_id.as_deref(), Some("none")) && matches!(match_result, DiscogsReleaseMatchResult::Unmatched(_)) {
@@ -191,11 +541,11 @@ impl DiscogsMatcher {
                         let refined_release = refined::DiscogsRelease::from(&serialized_release, None)?;
 
                         // FIXME: clone() is redundant here
-                        match Self::match_release_with_music_files(
+                        match self.match_release_with_music_files(
                             refined_release.clone(),
                             &music_files,
                             true,
-                        ) {
+                        )? {
                             None => {
                                 match Self::ask_for_release_id(
                                     &format!("Failed to match with ID {}", release_id)
@@ -206,9 +556,10 @@ impl DiscogsMatcher {
                                     Some(new_release_id) => release_id = new_release_id,
                                 }
                             }
-                            Some(tracks_matching) => {
+                            Some((tracks_matching, unmatched_music_files)) => {
                                 match_result = Matched {
                                     tracks_matching,
+                                    unmatched_music_files,
                                     release: refined_release,
                                 };
                                 break;
@@ -218,23 +569,80 @@ impl DiscogsMatcher {
                 }
             }
 
-            if let Matched { release, .. } = &match_result {
+            if let Matched {
+                release,
+                unmatched_music_files,
+                ..
+            } = &match_result
+            {
                 console_print!("Will use {}", release.uri.as_str().path_styled());
+                for music_file in unmatched_music_files {
+                    console_warn!(
+                        "{} {}",
+                        "Couldn't bind to any track, will use file tags as is:".warning_styled(),
+                        music_file.file_path.file_name_or_empty().path_styled()
+                    );
+                }
             } else {
                 console_print!("Will use file tags as is");
             }
 
+            console::get().emit(&ConsoleEvent::Matched {
+                path: path.display().to_string(),
+                release_uri: match &match_result {
+                    Matched { release, .. } => Some(release.uri.to_owned()),
+                    DiscogsReleaseMatchResult::Unmatched(_) => None,
+                },
+                unmatched_paths: match &match_result {
+                    Matched {
+                        unmatched_music_files,
+                        ..
+                    } => unmatched_music_files
+                        .iter()
+                        .map(|v| v.file_path.display().to_string())
+                        .collect_vec(),
+                    DiscogsReleaseMatchResult::Unmatched(music_files) => music_files
+                        .iter()
+                        .map(|v| v.file_path.display().to_string())
+                        .collect_vec(),
+                },
+            });
+
             result.push(match_result);
         }
 
+        pb.finish_and_clear();
+
         Ok(result)
     }
 
-    pub fn download_cover(&self, url: &str, path: &Path, pb: &ProgressBar) -> Result<()> {
-        let mut response = self.get_ok(url)?;
+    // Returns nothing when cover at existing path is known to be up to date
+    pub fn download_cover(
+        &self,
+        url: &str,
+        existing_path: Option<&Path>,
+        pb: &ProgressBar,
+    ) -> Result<Option<DownloadedCover>> {
+        let known_etag = existing_path.and_then(|path| {
+            self.cover_etags
+                .lock()
+                .ok()?
+                .get(path)
+                .filter(|v| v.uri == url)
+                .map(|v| v.etag.to_owned())
+        });
+        let mut response = self.get_ok_if_none_match(url, known_etag.as_deref())?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(ToOwned::to_owned);
 
-        let mut file =
-            &mut ProgressWriter::new(fs::File::create(path)?, |bytes| pb.inc(bytes as u64));
+        let mut data = Vec::new();
+        let mut writer = ProgressWriter::new(&mut data, |bytes| pb.inc(bytes as u64));
 
         pb.set_length(
             response
@@ -243,89 +651,387 @@ impl DiscogsMatcher {
         );
         pb.set_position(0);
 
-        response.copy_to(&mut file)?;
+        response.copy_to(&mut writer)?;
 
-        Ok(())
+        Ok(Some(DownloadedCover { data, etag }))
+    }
+
+    pub fn remember_cover_etag(&self, path: &Path, url: &str, etag: Option<String>) {
+        let Ok(mut cover_etags) = self.cover_etags.lock() else {
+            return;
+        };
+        match etag {
+            Some(etag) => cover_etags.insert(
+                path.to_owned(),
+                CoverEtag {
+                    uri: url.to_owned(),
+                    etag,
+                },
+            ),
+            None => cover_etags.remove(path),
+        };
+    }
+
+    fn is_tracks_count_acceptable(&self, tracks_count: usize, files_count: usize) -> bool {
+        // Bonus tracks, pre-gap hidden tracks and alike may reside in the same folder
+        tracks_count > 0
+            && files_count >= tracks_count
+            && files_count - tracks_count <= self.options.max_extra_files
+    }
+
+    fn print_tracks_count_mismatch(files_count: usize, tracks_counts: &[usize]) {
+        console_warn!(
+            "{} folder has {} file(s) while found releases have {} track(s)",
+            "Track count mismatch:".warning_styled(),
+            files_count,
+            tracks_counts.iter().sorted().dedup().join(", ")
+        );
+        // Box sets are often split into several releases on Discogs
+        if tracks_counts.iter().all(|v| *v < files_count) {
+            console_warn!(
+                "{}",
+                "Folder may span several Discogs releases, consider splitting it per disc"
+                    .warning_styled()
+            );
+        }
     }
 
     fn match_release_with_music_files<'a>(
+        &self,
         release: refined::DiscogsRelease,
         music_files: &Vec<&'a MusicFile>,
         simplified_match: bool,
-    ) -> Option<Vec<DiscogsTrackMatch<'a>>> {
+    ) -> Result<Option<(Vec<DiscogsTrackMatch<'a>>, Vec<&'a MusicFile>)>> {
         let track_list = release.tracks;
 
-        if track_list.is_empty() || track_list.len() != music_files.len() {
-            return None;
+        if !self.is_tracks_count_acceptable(track_list.len(), music_files.len()) {
+            return Ok(None);
         }
 
         let mut tracks_matching: Vec<DiscogsTrackMatch> = vec![];
+        let mut unmatched_music_files: Vec<&MusicFile> = vec![];
+        let mut bound_disc_positions = HashSet::new();
+        let mut swapped_music_files: Vec<&MusicFile> = vec![];
 
         for music_file in music_files {
             let tag = &music_file.tag;
-            let track_title = tag
+
+            // Hidden track in the pre-gap (HTOA) is usually ripped as track zero
+            if tag.track_number() == Some(0)
+                && music_files.len() - track_list.len() > unmatched_music_files.len()
+            {
+                unmatched_music_files.push(music_file);
+                continue;
+            }
+
+            let mut track_title = tag
                 .title()
                 .or_else(|| music_file.file_path.file_stem().and_then(|v| v.to_str()))
                 .unwrap_or_default();
+            if self.options.detect_swapped_tags {
+                let best_title_score = |title: &str| {
+                    track_list
+                        .iter()
+                        .filter(|track| !bound_disc_positions.contains(&(track.disc, track.position)))
+                        .map(|track| title.similarity_score(&track.title, &self.options.similarity))
+                        .fold(0f64, f64::max)
+                };
+                if let Some(artist) = tag.artist() {
+                    if best_title_score(artist) > best_title_score(track_title) {
+                        swapped_music_files.push(music_file);
+                        track_title = artist;
+                    }
+                }
+            }
             let sorted_by_title_similarity = track_list
                 .iter()
+                .filter(|track| !bound_disc_positions.contains(&(track.disc, track.position)))
                 .sorted_by(|a, b| {
+                    let similarity = &self.options.similarity;
                     track_title
-                        .similarity_score(&b.title)
-                        .partial_cmp(&track_title.similarity_score(&a.title))
-                        .unwrap()
+                        .similarity_score(&b.title, similarity)
+                        .total_cmp(&track_title.similarity_score(&a.title, similarity))
                 })
                 .collect_vec();
+            if let Some(best_track) = sorted_by_title_similarity.first() {
+                console_print_verbose!(
+                    VERBOSITY_MATCHING,
+                    "{} ~ {} ({:.2})",
+                    track_title.tag_styled(),
+                    best_track.title.as_str().tag_styled(),
+                    track_title.similarity_score(&best_track.title, &self.options.similarity)
+                );
+            }
             let Some(track) = sorted_by_title_similarity.iter().find(|track| {
                 let disc_position_matched = || tag.disc().unwrap_or(1) == track.disc && tag.track_number() == Some(track.position);
-                let title_matched = || track_title.is_similar(&track.title);
-                let duration_matched = || {
-                    const DURATION_DIFF_THRESHOLD: Duration = Duration::from_secs(30);
-                    let Some(mut duration1) = music_file.duration else { return false; };
-                    let Some(mut duration2) = track.duration else { return false; };
-                    if duration2 < duration1 { swap(&mut duration1, &mut duration2); };
-                    duration2 - duration1 < DURATION_DIFF_THRESHOLD
-                };
+                let title_matched = || track_title.is_similar(&track.title, &self.options.similarity);
+                let duration_matched = || durations_match(music_file.duration, track.duration, self.options.duration_tolerance);
                 if simplified_match {
                     disc_position_matched()
                 } else {
                     (title_matched() && duration_matched()) || (title_matched() && disc_position_matched())
                 }
             }) else {
-                return None;
+                if music_files.len() - track_list.len() > unmatched_music_files.len() {
+                    unmatched_music_files.push(music_file);
+                    continue;
+                } else {
+                    console_print_verbose!(
+                        VERBOSITY_MATCHING,
+                        "Rejected: no track matches {}",
+                        music_file.file_path.file_name_or_empty().path_styled()
+                    );
+                    return Ok(None);
+                }
             };
 
+            bound_disc_positions.insert((track.disc, track.position));
             tracks_matching.push(DiscogsTrackMatch {
                 music_file,
-                track: track.deref().clone(),
+                track: (*track).clone(),
+            })
+        }
+
+        if !swapped_music_files.is_empty() {
+            if !self.options.allow_questions {
+                console_print_verbose!(
+                    VERBOSITY_MATCHING,
+                    "Rejected: artist and title look swapped"
+                );
+                return Ok(None);
+            }
+            for music_file in &swapped_music_files {
+                console_warn!(
+                    "{} {}",
+                    "Artist and title look swapped:".warning_styled(),
+                    music_file.file_path.file_name_or_empty().path_styled()
+                );
+            }
+            let accepted = console::get().suspend(|| {
+                Confirm::new()
+                    .with_prompt(format!("Match {} treating them as swapped?", release.title))
+                    .default(true)
+                    .show_default(true)
+                    .wait_for_newline(true)
+                    .interact()
+            })?;
+            if !accepted {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some((tracks_matching, unmatched_music_files)))
+    }
+
+    // Last resort for well-named rips without usable tags, files follow the tracklist in name order
+    fn match_by_filename_order<'a>(
+        &self,
+        release: &refined::DiscogsRelease,
+        music_files: &[&'a MusicFile],
+    ) -> Result<Option<Vec<DiscogsTrackMatch<'a>>>> {
+        if !self.options.allow_questions {
+            return Ok(None);
+        }
+        let tracks_matching = music_files
+            .iter()
+            // Unpadded rips are numbered "2", "10" so plain string order would mix them up
+            .sorted_by(|a, b| {
+                a.file_path
+                    .file_name_or_empty()
+                    .natural_cmp(b.file_path.file_name_or_empty())
+            })
+            .zip(
+                release
+                    .tracks
+                    .iter()
+                    .sorted_by_key(|v| (v.disc, v.position)),
+            )
+            .map(|(music_file, track)| DiscogsTrackMatch {
+                music_file,
+                track: track.clone(),
             })
+            .collect_vec();
+
+        for track_match in &tracks_matching {
+            console_print!(
+                "{} → {}",
+                track_match.music_file.file_path.file_name_or_empty().path_styled(),
+                track_match.track.title.as_str().tag_styled()
+            );
         }
+        let accepted = console::get().suspend(|| {
+            Confirm::new()
+                .with_prompt(format!(
+                    "Titles don't match, assign {} tracks by file name order?",
+                    release.title
+                ))
+                .default(false)
+                .show_default(true)
+                .wait_for_newline(true)
+                .interact()
+        })?;
 
-        Some(tracks_matching)
+        Ok(accepted.then_some(tracks_matching))
     }
 
     fn search_master_release<'a>(
         &'a self,
         params: &'a [(&str, String)],
-    ) -> impl Iterator<Item=Result<(String, Option<serialized::DiscogsMaster>)>> + '_ {
+    ) -> impl Iterator<Item=Result<(String, Option<serialized::DiscogsMaster>)>> + 'a {
         std::iter::once_with(move || {
             let mut search_params: Vec<(&str, String)> = vec![("type", "master".to_owned())];
             search_params.extend_from_slice(params);
             self.fetch_search_results(search_params)
         })
-        .map_ok(|v| v.results)
-        .flatten_ok()
-            .map_ok(|v| -> Result<(String, Option<serialized::DiscogsMaster>)> {
-            let master: serialized::DiscogsMaster = self.fetch_by_url(v.resource_url)?;
-                Ok((master.main_release_url.to_owned(), Some(master)))
+        .map_ok(|v| {
+            // Resolve masters concurrently, only as many as could be actually checked
+            let results = v
+                .results
+                .into_iter()
+                .take(MAX_RELEASE_FETCHES_PER_PARAMS)
+                .collect_vec();
+            self.master_fetch_pool.install(|| {
+                results
+                    .into_par_iter()
+                    .map(|v| -> Result<(String, Option<serialized::DiscogsMaster>)> {
+                        let master = self.fetch_master(&v.resource_url)?;
+                        Ok((master.main_release_url.to_owned(), Some(master)))
+                    })
+                    .collect::<Vec<_>>()
+            })
         })
-        .flatten()
+        .flat_map(|v| match v {
+            Ok(results) => results,
+            Err(error) => vec![Err(error)],
+        })
+    }
+
+    fn should_skip_until_resumed(&self, path: &Path) -> bool {
+        let Some(resume_from) = &self.options.resume_from else {
+            return false;
+        };
+        // Disc subfolders of the resume folder are groups of their own so match by prefix
+        if self.is_resume_pending()
+            && path
+                .canonicalize()
+                .is_ok_and(|path| path.starts_with(resume_from))
+        {
+            self.resume_pending.store(false, Ordering::Relaxed);
+        }
+        self.is_resume_pending()
+    }
+
+    pub fn is_resume_pending(&self) -> bool {
+        self.resume_pending.load(Ordering::Relaxed)
+    }
+
+    fn fetch_master(&self, url: &str) -> Result<serialized::DiscogsMaster> {
+        let cached = self
+            .masters_cache
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(url).cloned());
+        if let Some(cached) = cached {
+            return Ok(cached.master);
+        }
+        let master: serialized::DiscogsMaster = self.fetch_by_url(url)?;
+        if let Ok(mut cache) = self.masters_cache.lock() {
+            let cached = CachedMaster {
+                fetched_at: Self::now_secs(),
+                master: master.clone(),
+            };
+            cache.insert(url.to_owned(), cached);
+        }
+        Ok(master)
+    }
+
+    fn get_masters_cache_file_path() -> Option<PathBuf> {
+        Some(dirs::cache_dir()?.join(MASTERS_CACHE_RELATIVE_PATH))
+    }
+
+    // Probability of a request being rate limited, 1.0 would never let any request through
+    fn simulated_rate_limit() -> Result<Option<f64>> {
+        let Ok(value) = env::var(SIMULATE_RATE_LIMIT_ENV) else {
+            return Ok(None);
+        };
+        let probability = value
+            .parse::<f64>()
+            .with_context(|| format!("Invalid {} value", SIMULATE_RATE_LIMIT_ENV))?;
+        if !(0f64..1f64).contains(&probability) {
+            bail!(
+                "{} must be in range [0, 1), got {}",
+                SIMULATE_RATE_LIMIT_ENV,
+                value
+            );
+        }
+        Ok(Some(probability))
+    }
+
+    fn load_masters_cache() -> HashMap<String, CachedMaster> {
+        let now = Self::now_secs();
+        let cache: HashMap<String, CachedMaster> = Self::get_masters_cache_file_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        cache
+            .into_iter()
+            .filter(|(_, cached)| {
+                now.saturating_sub(cached.fetched_at) < MASTERS_CACHE_TTL.as_secs()
+            })
+            .collect()
+    }
+
+    fn save_masters_cache(&self) -> Result<()> {
+        let path = Self::get_masters_cache_file_path().context("No cache directory")?;
+        let cache = self
+            .masters_cache
+            .lock()
+            .ok()
+            .context("Masters cache is poisoned")?;
+        Self::save_cache_file(&path, &*cache)
+    }
+
+    fn now_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|v| v.as_secs())
+            .unwrap_or_default()
+    }
+
+    // Several runs may share the cache, so never leave a half written file behind
+    fn save_cache_file(path: &Path, value: &impl Serialize) -> Result<()> {
+        fs::create_dir_all(path.parent_or_empty())?;
+        let mut temp_file = NamedTempFile::new_in(path.parent_or_empty())?;
+        temp_file.write_all(serde_json::to_string(value)?.as_bytes())?;
+        temp_file.persist(path)?;
+        Ok(())
+    }
+
+    fn get_cover_etags_file_path() -> Option<PathBuf> {
+        Some(dirs::cache_dir()?.join(COVER_ETAGS_RELATIVE_PATH))
+    }
+
+    fn load_cover_etags() -> HashMap<PathBuf, CoverEtag> {
+        Self::get_cover_etags_file_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_cover_etags(&self) -> Result<()> {
+        let path = Self::get_cover_etags_file_path().context("No cache directory")?;
+        let cover_etags = self
+            .cover_etags
+            .lock()
+            .ok()
+            .context("Cover ETags are poisoned")?;
+        Self::save_cache_file(&path, &*cover_etags)
     }
 
     fn search_release<'a>(
         &'a self,
         params: &'a [(&str, String)],
-    ) -> impl Iterator<Item=Result<(String, Option<serialized::DiscogsMaster>)>> + '_ {
+    ) -> impl Iterator<Item=Result<(String, Option<serialized::DiscogsMaster>)>> + 'a {
         std::iter::once_with(move || {
             let mut search_params: Vec<(&str, String)> = vec![("type", "release".to_owned())];
             search_params.extend_from_slice(params);
@@ -336,14 +1042,181 @@ impl DiscogsMatcher {
             .map_ok(|v| (v.resource_url, None))
     }
 
+    // Lets user fix albums split across sibling folders or folders mixing several albums
+    // Searching with several albums joined into one query only produces garbage matches
+    fn split_mixed_albums<'a, 'b>(
+        &self,
+        groups: Vec<(&'a Path, Vec<&'b MusicFile>)>,
+    ) -> Result<Vec<(&'a Path, Vec<&'b MusicFile>)>> {
+        let mut result = Vec::new();
+        for (path, music_files) in groups {
+            let albums = music_files
+                .iter()
+                .filter_map(|v| v.tag.album())
+                .map(album_without_disc_suffix)
+                .filter(|v| !v.is_empty())
+                .unique()
+                .collect_vec();
+            if albums.len() <= 1 {
+                result.push((path, music_files));
+                continue;
+            }
+
+            console_warn!(
+                "{} {} ({})",
+                "Folder mixes several albums:".warning_styled(),
+                path.display().path_styled(),
+                albums.join(", ").tag_styled()
+            );
+            let split = match self.options.mixed_album_folders {
+                MixedAlbumFolders::Split => true,
+                MixedAlbumFolders::Keep => false,
+                MixedAlbumFolders::Prompt if !self.options.allow_questions => false,
+                MixedAlbumFolders::Prompt => console::get().suspend(|| {
+                    Confirm::new()
+                        .with_prompt("Split it into one group per album?")
+                        .default(true)
+                        .show_default(true)
+                        .wait_for_newline(true)
+                        .interact()
+                })?,
+            };
+            if !split {
+                result.push((path, music_files));
+                continue;
+            }
+
+            music_files
+                .into_iter()
+                .into_group_map_by(|v| album_without_disc_suffix(v.tag.album().unwrap_or_default()))
+                .into_iter()
+                .sorted_by(|(a, _), (b, _)| a.cmp(b))
+                .for_each(|(_, music_files)| result.push((path, music_files)));
+        }
+        Ok(result)
+    }
+
+    fn review_grouping<'a, 'b>(
+        mut groups: Vec<(&'a Path, Vec<&'b MusicFile>)>,
+    ) -> Result<Vec<(&'a Path, Vec<&'b MusicFile>)>> {
+        console::get().ensure_interactive("Review grouping")?;
+        loop {
+            let group_names = groups
+                .iter()
+                .map(|(path, music_files)| {
+                    format!("{} ({} file(s))", path.display(), music_files.len())
+                })
+                .collect_vec();
+            for group_name in &group_names {
+                console_print!("{}", group_name.as_str().path_styled());
+            }
+
+            let selected = Select::new()
+                .with_prompt("Review grouping".styled().yellow().to_string())
+                .default(0)
+                .item("Continue")
+                .item("Merge groups")
+                .item("Split group")
+                .interact()?;
+
+            match selected {
+                1 => {
+                    let selected = MultiSelect::new()
+                        .with_prompt("Select groups to merge")
+                        .items(&group_names)
+                        .interact()?;
+                    let Some((&first, rest)) = selected.split_first() else {
+                        continue;
+                    };
+                    for &index in rest {
+                        let music_files = mem::take(&mut groups[index].1);
+                        groups[first].1.extend(music_files);
+                    }
+                    groups.retain(|(_, music_files)| !music_files.is_empty());
+                }
+                2 => {
+                    let index = Select::new()
+                        .with_prompt("Select group to split")
+                        .default(0)
+                        .items(&group_names)
+                        .interact()?;
+                    let (path, music_files) = &groups[index];
+                    let selected = MultiSelect::new()
+                        .with_prompt("Select files to move into new group")
+                        .items(
+                            music_files
+                                .iter()
+                                .map(|v| v.file_path.file_name_or_empty())
+                                .collect_vec(),
+                        )
+                        .interact()?;
+                    if selected.is_empty() || selected.len() == music_files.len() {
+                        continue;
+                    }
+                    let path = *path;
+                    let (moved, kept): (Vec<_>, Vec<_>) = mem::take(&mut groups[index].1)
+                        .into_iter()
+                        .enumerate()
+                        .partition(|(index, _)| selected.contains(index));
+                    groups[index].1 = kept.into_iter().map(|v| v.1).collect_vec();
+                    let moved = moved.into_iter().map(|v| v.1).collect_vec();
+                    groups.insert(index + 1, (path, moved));
+                }
+                _ => break Ok(groups),
+            }
+        }
+    }
+
+    fn catalog_number_from_path(&self, path: &Path) -> Option<String> {
+        let regex = self.options.catalog_number_regex.as_ref()?;
+        // Check disc subfolder's parent too, e.g. "Artist - Album [LABEL-1234]/CD1"
+        path.ancestors().take(2).find_map(|path| {
+            let captures = regex.captures(path.file_name_or_empty())?;
+            captures
+                .get(1)
+                .or_else(|| captures.get(0))
+                .map(|v| v.as_str().trim().to_owned())
+        })
+    }
+
+    // Pattern components are matched against the innermost folders, e.g. "{artist}/{album}"
+    // takes the folder name as album and its parent's name as artist. Use "{_}" to skip a level.
+    fn folder_tags_from_path(&self, path: &Path) -> FolderTags {
+        let mut result = FolderTags::default();
+        let Some(pattern) = &self.options.folder_tags_pattern else {
+            return result;
+        };
+        for (placeholder, path) in pattern.rsplit('/').zip(path.ancestors()) {
+            let value = Some(path.file_name_or_empty().to_owned()).filter(|v| !v.is_empty());
+            match placeholder.trim() {
+                "{artist}" => result.artist = value,
+                "{album}" => result.album = value,
+                _ => {}
+            }
+        }
+        result
+    }
+
     fn common_search_params_from_music_files(
         music_files: &[&MusicFile],
+        catalog_number: Option<String>,
+        folder_tags: &FolderTags,
+        similarity_options: &SimilarityOptions,
+        format_filter: Option<&str>,
     ) -> Vec<Vec<(&'static str, String)>> {
         let artist = (
             "artist",
             music_files
                 .iter()
-                .filter_map(|v| v.tag.artist().map(StringExtensions::simplify))
+                .flat_map(|v| {
+                    let artists = v.tag.artists();
+                    if artists.is_empty() {
+                        folder_tags.artist.as_deref().into_iter().collect_vec()
+                    } else {
+                        artists
+                    }
+                })
+                .map(|v| v.simplify(similarity_options))
                 .unique()
                 .join(" "),
         );
@@ -351,7 +1224,12 @@ impl DiscogsMatcher {
             "release_title",
             music_files
                 .iter()
-                .filter_map(|v| v.tag.album().map(StringExtensions::simplify))
+                .filter_map(|v| {
+                    v.tag
+                        .album()
+                        .or(folder_tags.album.as_deref())
+                        .map(|v| v.simplify(similarity_options))
+                })
                 .unique()
                 .join(" "),
         );
@@ -363,18 +1241,40 @@ impl DiscogsMatcher {
                 .unique()
                 .join(" "),
         );
-        vec![
+        let mut result = vec![
             vec![album.clone(), year.clone()],
             vec![album.clone()],
             vec![artist.clone(), album.clone(), year.clone()],
             vec![artist.clone(), album.clone()],
             vec![artist.clone()],
-        ]
+        ];
+        if let Some(catalog_number) = catalog_number {
+            let catalog_number = ("catno", catalog_number);
+            result.insert(0, vec![catalog_number.clone()]);
+            result.insert(0, vec![artist.clone(), catalog_number]);
+        }
+        if let Some(format_filter) = format_filter {
+            for params in &mut result {
+                params.push(("format", format_filter.to_owned()));
+            }
+        }
+        result
     }
 
     fn fetch_release_by_id(&self, release_id: &str) -> Result<serialized::DiscogsRelease> {
         let url = &format!("https://api.discogs.com/releases/{}", release_id);
-        self.fetch_by_url(url)
+        self.fetch_release_by_url(url)
+    }
+
+    fn fetch_release_by_url<U>(&self, url: U) -> Result<serialized::DiscogsRelease>
+    where
+        U: IntoUrl + Clone + Display,
+    {
+        let json = self.get_ok(url)?.json::<serde_json::Value>()?;
+        if self.options.print_release_json {
+            eprintln!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        Ok(serde_json::from_value(json)?)
     }
 
     fn fetch_search_results<I, K, V>(
@@ -388,7 +1288,19 @@ impl DiscogsMatcher {
         V: AsRef<str>,
     {
         let url = Url::parse_with_params("https://api.discogs.com/database/search", params)?;
-        self.fetch_by_url(url)
+        let query = url
+            .query_pairs()
+            .map(|(key, value)| format!("{}={:?}", key, value))
+            .join(" ");
+        let page: serialized::DiscogsSearchResultPage = self.fetch_by_url(url)?;
+        if self.options.dump_search_queries {
+            console_print!(
+                "Search {} returned {} result(s)",
+                query.tag_styled(),
+                page.results.len()
+            );
+        }
+        Ok(page)
     }
 
     fn fetch_by_url<U, T>(&self, url: U) -> Result<T>
@@ -401,12 +1313,64 @@ impl DiscogsMatcher {
         )?)
     }
 
+    // Deterministic rather than random: fraction accumulates per response and each time it
+    // reaches a whole one the response is rate limited, e.g. 0.3 limits 3 of every 10 responses
+    fn next_response_rate_limited(&self) -> bool {
+        let Some(fraction) = self.simulated_rate_limit else {
+            return false;
+        };
+        let Ok(mut debt) = self.simulated_rate_limit_debt.lock() else {
+            return false;
+        };
+        *debt += fraction;
+        if *debt >= 1f64 {
+            *debt -= 1f64;
+            true
+        } else {
+            false
+        }
+    }
+
     fn get_ok<T: IntoUrl + Clone + Display>(&self, url: T) -> Result<Response> {
+        self.get_ok_if_none_match(url, None)
+    }
+
+    // Not modified response is considered successful as well
+    fn get_ok_if_none_match<T: IntoUrl + Clone + Display>(
+        &self,
+        url: T,
+        etag: Option<&str>,
+    ) -> Result<Response> {
         console_print!("Fetching {}", (&url).path_styled());
         loop {
-            let response = self.http_client.get(url.clone()).send()?;
-            let status = response.status();
-            if status.is_success() {
+            let mut request = self.http_client.get(url.clone());
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            let response = request.send()?;
+            let header_as_str = |header| {
+                response
+                    .headers()
+                    .get(header)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("?")
+            };
+            console_print_verbose!(
+                VERBOSITY_HTTP,
+                "GET {} {} (rate limit used {}/{}, remaining {})",
+                url,
+                response.status(),
+                header_as_str("X-Discogs-Ratelimit-Used"),
+                header_as_str("X-Discogs-Ratelimit"),
+                header_as_str("X-Discogs-Ratelimit-Remaining")
+            );
+            let simulated = self.next_response_rate_limited();
+            let status = if simulated {
+                StatusCode::TOO_MANY_REQUESTS
+            } else {
+                response.status()
+            };
+            if status.is_success() || status == StatusCode::NOT_MODIFIED {
                 break Ok(response);
             } else if status == StatusCode::TOO_MANY_REQUESTS {
                 console_print!(
@@ -423,8 +1387,14 @@ impl DiscogsMatcher {
                         .map(|v| -> Result<f64> { Ok(v.to_str()?.parse::<f64>()?) })
                         .with_context(|| format!("No required header: {}", header))?
                 };
-                let rate_limit = header_as_number("X-Discogs-Ratelimit")?;
-                let rate_limit_used = header_as_number("X-Discogs-Ratelimit-Used")?;
+                let (rate_limit, rate_limit_used) = if simulated {
+                    (SIMULATED_RATE_LIMIT, SIMULATED_RATE_LIMIT)
+                } else {
+                    (
+                        header_as_number("X-Discogs-Ratelimit")?,
+                        header_as_number("X-Discogs-Ratelimit-Used")?,
+                    )
+                };
                 let skip = f64::min(rate_limit_used - rate_limit, 0f64) + 1f64;
                 thread::sleep(Duration::from_secs_f64(skip * 60f64 / rate_limit));
             } else {
@@ -434,28 +1404,55 @@ impl DiscogsMatcher {
     }
 
     fn ask_for_release_id(reason: &str) -> Result<Option<String>> {
-        let selected = Select::new()
-            .with_prompt(reason.styled().yellow().to_string())
-            .default(0)
-            .item("Enter Discogs ID")
-            .item("Take as is")
-            .interact()?;
-
-        match selected {
-            0 => Input::new()
-                .with_prompt(
-                    "Please enter Discogs release ID"
-                        .styled()
-                        .bold()
-                        .to_string(),
-                )
-                .interact_text()
-                .context("Failed to interact")
-                .and_then(|v: String| Self::extract_discogs_id(&v).map(ToOwned::to_owned))
-                .map(Some),
-            1 => Ok(None),
-            _ => bail!("Unsupported option"),
-        }
+        console::get().ensure_interactive(reason)?;
+        console::get().suspend(|| {
+            let selected = Select::new()
+                .with_prompt(reason.styled().yellow().to_string())
+                .default(0)
+                .item("Enter Discogs ID")
+                .item("Take as is")
+                .interact()?;
+
+            match selected {
+                0 => Input::new()
+                    .with_prompt(
+                        "Please enter Discogs release ID"
+                            .styled()
+                            .bold()
+                            .to_string(),
+                    )
+                    .interact_text()
+                    .context("Failed to interact")
+                    .and_then(|v: String| Self::extract_discogs_id(&v).map(ToOwned::to_owned))
+                    .map(Some),
+                1 => Ok(None),
+                _ => bail!("Unsupported option"),
+            }
+        })
+    }
+
+    fn release_id_from_tags(music_files: &[&MusicFile]) -> Option<String> {
+        #[allow(clippy::unwrap_used)]
+        let regex = Regex::new(r"/release/([0-9]+)").unwrap();
+
+        let id_from_uri = music_files
+            .iter()
+            .map(|v| v.tag.custom_text(DISCOGS_RELEASE_TAG))
+            .all_equal_value()
+            .ok()
+            .flatten()
+            .and_then(|uri| Some(regex.captures(uri)?.get(1)?.as_str().to_owned()));
+
+        // Files tagged by other tools may carry the numeric ID only
+        id_from_uri.or_else(|| {
+            music_files
+                .iter()
+                .map(|v| v.tag.custom_text(DISCOGS_RELEASE_ID_TAG))
+                .all_equal_value()
+                .ok()?
+                .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+                .map(ToOwned::to_owned)
+        })
     }
 
     fn extract_discogs_id(string: &str) -> Result<&str> {
@@ -471,3 +1468,61 @@ impl DiscogsMatcher {
         }
     }
 }
+
+// Discs of one album are often tagged "Album (Disc 1)", "Album (Disc 2)" and must not be split
+fn album_without_disc_suffix(album: &str) -> String {
+    DISC_SUFFIX_REGEX
+        .replace(album.trim(), "")
+        .trim()
+        .to_owned()
+}
+
+// Difference may be up to tolerance inclusive, so zero tolerance requires an exact match
+fn durations_match(
+    duration1: Option<Duration>,
+    duration2: Option<Duration>,
+    tolerance: Duration,
+) -> bool {
+    let (Some(mut duration1), Some(mut duration2)) = (duration1, duration2) else {
+        return false;
+    };
+    if duration2 < duration1 {
+        swap(&mut duration1, &mut duration2);
+    }
+    duration2 - duration1 <= tolerance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_match_within_tolerance() {
+        let tolerance = Duration::from_secs(5);
+        let track = Some(Duration::from_secs(200));
+        assert!(durations_match(Some(Duration::from_secs(204)), track, tolerance));
+        assert!(durations_match(Some(Duration::from_secs(196)), track, tolerance));
+        assert!(durations_match(Some(Duration::from_secs(205)), track, tolerance));
+        assert!(durations_match(Some(Duration::from_secs(195)), track, tolerance));
+        assert!(!durations_match(Some(Duration::from_secs(206)), track, tolerance));
+        assert!(!durations_match(Some(Duration::from_secs(194)), track, tolerance));
+        assert!(!durations_match(None, track, tolerance));
+        assert!(!durations_match(track, None, tolerance));
+    }
+
+    #[test]
+    fn durations_match_exactly_with_zero_tolerance() {
+        let track = Some(Duration::from_secs(200));
+        assert!(durations_match(track, track, Duration::ZERO));
+        assert!(!durations_match(Some(Duration::from_millis(200_001)), track, Duration::ZERO));
+    }
+
+    #[test]
+    fn disc_suffix_is_stripped_from_album() {
+        assert_eq!(album_without_disc_suffix("Album (Disc 1)"), "Album");
+        assert_eq!(album_without_disc_suffix("Album [CD2]"), "Album");
+        assert_eq!(album_without_disc_suffix("Album disk 3 "), "Album");
+        assert_eq!(album_without_disc_suffix("Album CD"), "Album CD");
+        assert_eq!(album_without_disc_suffix("Discography"), "Discography");
+    }
+}
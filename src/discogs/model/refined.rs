@@ -1,30 +1,53 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::iter;
 use std::time::Duration;
 
 use anyhow::Result;
 use itertools::Itertools;
+use once_cell::sync::Lazy;
 use regex::Regex;
 
-use crate::console_print;
+use crate::console_warn;
 use crate::discogs::model::serialized;
 use crate::util::console_styleable::ConsoleStyleable;
 
+#[allow(clippy::unwrap_used)]
+static SIDE_POSITION_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^([A-Z])\d*(?:[a-z.]\w*)?$").unwrap());
+
+// In order of preference
+const COVER_IMAGE_KINDS: [&str; 2] = ["primary", "secondary"];
+
 #[derive(Clone)]
 pub struct DiscogsRelease {
+    pub id: u64,
     pub uri: String,
     pub title: String,
-    pub year: i32,
+    pub year: Option<i32>,
     pub styles: Option<Vec<String>>,
     pub image: Option<DiscogsImage>,
+    pub extra_images: Vec<DiscogsImage>,
     pub tracks: Vec<DiscogsTrack>,
     pub disc_to_total_tracks: HashMap<u32, u32>,
     pub artists: Vec<DiscogsArtist>,
+    // Credits like producer or remixer, release level ones may refer only to some of the tracks
+    pub extra_artists: Vec<DiscogsArtist>,
+    pub kind: DiscogsReleaseKind,
+    pub label: Option<String>,
+    pub country: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DiscogsReleaseKind {
+    Album,
+    Ep,
+    Single,
 }
 
 #[derive(Clone)]
 pub struct DiscogsImage {
     pub url: String,
+    pub kind: String,
 }
 
 #[derive(Clone)]
@@ -34,12 +57,17 @@ pub struct DiscogsTrack {
     pub disc: u32,
     pub duration: Option<Duration>,
     pub artists: Option<Vec<DiscogsArtist>>,
+    pub extra_artists: Vec<DiscogsArtist>,
+    pub isrc: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct DiscogsArtist {
     pub name: String,
     pub join: Option<String>,
+    pub role: Option<String>,
+    // Positions a release level credit is limited to, like "A1, B2" or "1 to 3"
+    pub tracks: Option<String>,
 }
 
 impl DiscogsRelease {
@@ -47,11 +75,18 @@ impl DiscogsRelease {
         let tracks = Self::tracks(serialized_release)?;
         let disc_to_total_tracks = Self::disc_to_total_tracks(&tracks);
         Ok(DiscogsRelease {
+            id: serialized_release.id,
             uri: serialized_release.uri.clone(),
             title: Self::title(serialized_release),
-            year: serialized_master.map(|v| v.year).unwrap_or_else(|| serialized_release.year),
+            // Discogs reports unknown year as 0
+            year: serialized_master
+                .map(|v| v.year)
+                .filter(|v| *v > 0)
+                .or(Some(serialized_release.year))
+                .filter(|v| *v > 0),
             styles: serialized_release.styles.clone(),
             image: Self::image(serialized_release),
+            extra_images: Self::extra_images(serialized_release),
             tracks,
             disc_to_total_tracks,
             artists: serialized_release
@@ -59,52 +94,159 @@ impl DiscogsRelease {
                 .iter()
                 .map(DiscogsArtist::from)
                 .collect_vec(),
+            extra_artists: DiscogsArtist::from_extra(&serialized_release.extraartists),
+            kind: Self::kind(serialized_release),
+            label: serialized_release
+                .labels
+                .iter()
+                .flatten()
+                .next()
+                .map(|v| strip_disambiguation(&v.name)),
+            country: serialized_release.country.clone(),
         })
     }
 
+    fn kind(serialized: &serialized::DiscogsRelease) -> DiscogsReleaseKind {
+        let descriptions = serialized
+            .formats
+            .iter()
+            .flatten()
+            .flat_map(|v| v.descriptions.iter().flatten())
+            .collect_vec();
+        if descriptions
+            .iter()
+            .any(|v| *v == "Single" || *v == "Maxi-Single")
+        {
+            DiscogsReleaseKind::Single
+        } else if descriptions.iter().any(|v| *v == "EP") {
+            DiscogsReleaseKind::Ep
+        } else {
+            DiscogsReleaseKind::Album
+        }
+    }
+
     fn title(serialized: &serialized::DiscogsRelease) -> String {
-        return serialized.title.trim().to_owned();
+        serialized.title.trim().to_owned()
     }
 
     fn image(serialized: &serialized::DiscogsRelease) -> Option<DiscogsImage> {
         let images = serialized.images.iter().flatten();
-        images
-            .clone()
-            .find(|v| v.type_ == "primary")
-            .or_else(|| images.clone().find(|v| v.type_ == "secondary"))
+        COVER_IMAGE_KINDS
+            .iter()
+            .find_map(|kind| images.clone().find(|v| v.type_ == *kind))
+            // Whether image of other kind is acceptable is decided by the caller
+            .or_else(|| images.clone().next())
+            .map(DiscogsImage::from)
+    }
+
+    // Scans other than the cover in the order Discogs lists them, usually back cover then booklet
+    fn extra_images(serialized: &serialized::DiscogsRelease) -> Vec<DiscogsImage> {
+        let cover_url = Self::image(serialized).map(|v| v.url);
+        serialized
+            .images
+            .iter()
+            .flatten()
             .map(DiscogsImage::from)
+            .filter(|v| Some(&v.url) != cover_url.as_ref())
+            .collect_vec()
     }
 
     fn tracks(serialized: &serialized::DiscogsRelease) -> Result<Vec<DiscogsTrack>> {
         const DEFAULT_DISC: u32 = 1;
 
-        let serialized_tracks = Self::extract_track_list(&serialized.tracklist).collect_vec();
+        // Headings only annotate disc context, index rows are flattened into their parts
+        let mut serialized_tracks = Vec::new();
+        let mut heading_disc = DEFAULT_DISC;
+        for (row_index, row) in serialized.tracklist.iter().enumerate() {
+            match row.type_.as_str() {
+                "heading" => {
+                    if let Some(disc) = DiscogsTrack::disc_from_heading(row) {
+                        heading_disc = disc;
+                    }
+                }
+                "index" => serialized_tracks.extend(
+                    Self::extract_track_list(row.sub_tracks.iter().flatten())
+                        .map(|v| (v, heading_disc, Some(row_index))),
+                ),
+                _ => serialized_tracks.extend(
+                    Self::extract_track_list(iter::once(row)).map(|v| (v, heading_disc, None)),
+                ),
+            }
+        }
+
+        // Vinyl sides continue numbering of the previous ones: A1..A6, B1.. become 1..6, 7..
+        // Tracks are counted rather than parsed so sub-tracks like "A3a", "A3b" stay distinct
+        let mut side_to_track_count = BTreeMap::new();
+        let side_ordinals = serialized_tracks
+            .iter()
+            .map(|(serialized_track, _, _)| {
+                let side = DiscogsTrack::side(serialized_track)?;
+                let count = side_to_track_count.entry(side).or_insert(0u32);
+                *count += 1;
+                Some((side, *count))
+            })
+            .collect_vec();
+        let side_to_position_offset: HashMap<char, u32> = side_to_track_count
+            .iter()
+            .scan(0, |offset, (side, count)| {
+                let side_offset = *offset;
+                *offset += count;
+                Some((*side, side_offset))
+            })
+            .collect();
 
         let mut refined_tracks = Vec::new();
 
+        let mut current_disc = DEFAULT_DISC;
         let mut track_index_position = 0u32;
+        let mut disc_to_position_offset: HashMap<u32, u32> = HashMap::new();
+        let mut last_index_track: Option<(usize, u32, u32)> = None;
         let mut used_indexing = false;
         let mut used_parsed_position = false;
-        for serialized_track in serialized_tracks {
+        for ((serialized_track, heading_disc, index_row), side_ordinal) in
+            serialized_tracks.into_iter().zip(side_ordinals)
+        {
+            if heading_disc != current_disc {
+                current_disc = heading_disc;
+                track_index_position = 0;
+            }
+            let side_position =
+                side_ordinal.map(|(side, ordinal)| side_to_position_offset[&side] + ordinal);
+            let parsed_disc_position = if let Some(position) = side_position {
+                Ok(Some((None, position)))
+            } else if index_row.is_some() {
+                DiscogsTrack::index_disc_position(serialized_track)
+            } else {
+                DiscogsTrack::disc_position(serialized_track)
+            };
             let (disc, position) = if let Some((disc, position)) =
-                DiscogsTrack::disc_position(serialized_track).ok().flatten()
+                parsed_disc_position.ok().flatten()
             {
                 if used_indexing {
-                    console_print!(
+                    console_warn!(
                         "{}",
                         "Tried to use parsed position while used indexing already".warning_styled()
                     )
                 } else {
                     used_parsed_position = true;
                 }
-                if let Some(disc) = disc {
-                    (disc, position)
-                } else {
-                    (DEFAULT_DISC, position)
+                let disc = disc.unwrap_or(current_disc);
+                let position_offset = disc_to_position_offset.entry(disc).or_default();
+                match last_index_track {
+                    // Parts of the same index share parsed position ("3a", "3b"), so shift them
+                    Some((last_index_row, last_disc, last_position))
+                        if side_position.is_none()
+                            && Some(last_index_row) == index_row
+                            && last_disc == disc =>
+                    {
+                        *position_offset += 1;
+                        (disc, last_position + 1)
+                    }
+                    _ => (disc, position + *position_offset),
                 }
             } else {
                 if used_parsed_position {
-                    console_print!(
+                    console_warn!(
                         "{}",
                         "Tried to use indexing while used parsed position already".warning_styled()
                     )
@@ -112,8 +254,9 @@ impl DiscogsRelease {
                     used_indexing = true;
                 }
                 track_index_position += 1;
-                (DEFAULT_DISC, track_index_position)
+                (current_disc, track_index_position)
             };
+            last_index_track = index_row.map(|index_row| (index_row, disc, position));
             refined_tracks.push(DiscogsTrack::from(serialized_track, position, disc)?)
         }
 
@@ -149,8 +292,13 @@ impl DiscogsImage {
     fn from(serialized: &serialized::DiscogsImage) -> DiscogsImage {
         DiscogsImage {
             url: serialized.resource_url.clone(),
+            kind: serialized.type_.clone(),
         }
     }
+
+    pub fn is_cover_kind(&self) -> bool {
+        COVER_IMAGE_KINDS.contains(&self.kind.as_str())
+    }
 }
 
 impl DiscogsTrack {
@@ -168,11 +316,18 @@ impl DiscogsTrack {
                 .artists
                 .as_ref()
                 .map(|v| v.iter().map(DiscogsArtist::from).collect_vec()),
+            extra_artists: DiscogsArtist::from_extra(&serialized.extraartists),
+            isrc: serialized
+                .isrc
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(ToOwned::to_owned),
         })
     }
 
     fn title(serialized: &serialized::DiscogsTrack) -> String {
-        return serialized.title.trim().to_owned();
+        serialized.title.trim().to_owned()
     }
 
     fn duration(serialized: &serialized::DiscogsTrack) -> Result<Option<Duration>> {
@@ -194,9 +349,43 @@ impl DiscogsTrack {
     }
 
     fn disc_position(serialized: &serialized::DiscogsTrack) -> Result<Option<(Option<u32>, u32)>> {
-        serialized
-            .position
-            .as_ref()
+        Self::parse_disc_position(serialized.position.as_deref())
+    }
+
+    // Index parts are positioned like "3a", "3.1" or "A3a", only the leading number is meaningful
+    fn index_disc_position(
+        serialized: &serialized::DiscogsTrack,
+    ) -> Result<Option<(Option<u32>, u32)>> {
+        Self::parse_disc_position(serialized.position.as_deref().map(|position| {
+            let position = position
+                .trim()
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic());
+            position
+                .split(|c: char| !c.is_ascii_digit() && c != '-')
+                .next()
+                .unwrap_or(position)
+        }))
+    }
+
+    // Vinyl and cassette positions are side letter and number ("A1", "B2", "C3a"), lone "A" included
+    fn side(serialized: &serialized::DiscogsTrack) -> Option<char> {
+        let captures = SIDE_POSITION_REGEX.captures(serialized.position.as_deref()?.trim())?;
+        captures.get(1)?.as_str().chars().next()
+    }
+
+    fn disc_from_heading(serialized: &serialized::DiscogsTrack) -> Option<u32> {
+        #[allow(clippy::unwrap_used)]
+        let regex = Regex::new(r"(?i)\b(?:cd|disc|disk)\s*(\d+)\b").unwrap();
+        regex
+            .captures(&serialized.title)?
+            .get(1)?
+            .as_str()
+            .parse::<u32>()
+            .ok()
+    }
+
+    fn parse_disc_position(position: Option<&str>) -> Result<Option<(Option<u32>, u32)>> {
+        position
             .map(|position| {
                 position
                     .split('-')
@@ -219,22 +408,205 @@ impl DiscogsArtist {
         DiscogsArtist {
             name: Self::name(serialized),
             join: serialized.join.clone(),
+            role: serialized
+                .role
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(ToOwned::to_owned),
+            tracks: serialized
+                .tracks
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(ToOwned::to_owned),
         }
     }
 
+    fn from_extra(serialized: &Option<Vec<serialized::DiscogsArtist>>) -> Vec<DiscogsArtist> {
+        serialized
+            .iter()
+            .flatten()
+            .map(DiscogsArtist::from)
+            .collect_vec()
+    }
+
+    // Roles may carry qualifiers like "Remix [Additional]" or list several duties at once
+    pub fn has_role(&self, role: &str) -> bool {
+        self.role.iter().flat_map(|v| v.split(',')).any(|v| {
+            v.split('[')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .eq_ignore_ascii_case(role)
+        })
+    }
+
     fn name(serialized: &serialized::DiscogsArtist) -> String {
-        let name = &serialized.name;
-        #[allow(clippy::unwrap_used)]
-        let regex = Regex::new(r".*( \(\d+\))").unwrap();
-        match regex.captures(name) {
-            Some(captures) => {
-                #[allow(clippy::unwrap_used)]
-                let range = captures.get(1).unwrap().range();
-                &name[..range.start]
-            }
-            None => name,
+        strip_disambiguation(&serialized.name)
+    }
+}
+
+// Discogs disambiguates same-named entities with numeric suffix like "Name (2)"
+fn strip_disambiguation(name: &str) -> String {
+    #[allow(clippy::unwrap_used)]
+    let regex = Regex::new(r".*( \(\d+\))").unwrap();
+    match regex.captures(name) {
+        Some(captures) => {
+            #[allow(clippy::unwrap_used)]
+            let range = captures.get(1).unwrap().range();
+            &name[..range.start]
         }
-        .trim()
-        .to_owned()
+        None => name,
+    }
+    .trim()
+    .to_owned()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn track(position: &str) -> serde_json::Value {
+        serde_json::json!({ "title": position, "type_": "track", "position": position })
+    }
+
+    fn index(title: &str, positions: &[&str]) -> serde_json::Value {
+        let sub_tracks = positions.iter().copied().map(track).collect_vec();
+        serde_json::json!({ "title": title, "type_": "index", "position": "", "sub_tracks": sub_tracks })
+    }
+
+    fn heading(title: &str) -> serde_json::Value {
+        serde_json::json!({ "title": title, "type_": "heading", "position": "" })
+    }
+
+    fn release_with_positions(positions: &[&str]) -> DiscogsRelease {
+        release_with_tracklist(positions.iter().copied().map(track).collect_vec())
+    }
+
+    fn release_with_tracklist(tracklist: Vec<serde_json::Value>) -> DiscogsRelease {
+        let serialized = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "title": "Album",
+            "uri": "https://www.discogs.com/release/1",
+            "tracklist": tracklist,
+            "artists": [],
+            "year": 2000,
+        }))
+        .unwrap();
+        DiscogsRelease::from(&serialized, None).unwrap()
+    }
+
+    fn disc_positions(release: &DiscogsRelease) -> Vec<(u32, u32)> {
+        release
+            .tracks
+            .iter()
+            .map(|v| (v.disc, v.position))
+            .collect()
+    }
+
+    #[test]
+    fn double_lp_sides_are_numbered_sequentially() {
+        let positions = ["A", "B", "C", "D"]
+            .iter()
+            .flat_map(|side| (1..=6).map(move |number| format!("{}{}", side, number)))
+            .collect_vec();
+        let release = release_with_positions(&positions.iter().map(String::as_str).collect_vec());
+        assert_eq!(
+            disc_positions(&release),
+            (1..=24).map(|position| (1, position)).collect_vec()
+        );
+        assert_eq!(release.disc_to_total_tracks[&1], 24);
+    }
+
+    #[test]
+    fn uneven_sides_continue_numbering() {
+        let release = release_with_positions(&["A1", "A2", "A3", "B1", "B2", "C", "D1"]);
+        assert_eq!(
+            disc_positions(&release),
+            vec![(1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (1, 6), (1, 7)]
+        );
+    }
+
+    #[test]
+    fn side_sub_tracks_get_distinct_positions() {
+        let release = release_with_positions(&["A1", "A2", "A3a", "A3b", "B1"]);
+        assert_eq!(
+            disc_positions(&release),
+            vec![(1, 1), (1, 2), (1, 3), (1, 4), (1, 5)]
+        );
+    }
+
+    #[test]
+    fn numeric_positions_are_kept() {
+        let release = release_with_positions(&["1", "2", "3"]);
+        assert_eq!(disc_positions(&release), vec![(1, 1), (1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn index_parts_are_flattened_with_distinct_positions() {
+        let release = release_with_tracklist(vec![
+            track("1"),
+            track("2"),
+            index("Medley", &["3a", "3b", "3c"]),
+            track("4"),
+        ]);
+        assert_eq!(
+            disc_positions(&release),
+            vec![(1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (1, 6)]
+        );
+    }
+
+    #[test]
+    fn headings_reset_disc_context() {
+        let release = release_with_tracklist(vec![
+            heading("CD 1"),
+            track("1"),
+            track("2"),
+            heading("CD 2"),
+            index("Suite", &["1.1", "1.2"]),
+            track("2"),
+        ]);
+        assert_eq!(
+            disc_positions(&release),
+            vec![(1, 1), (1, 2), (2, 1), (2, 2), (2, 3)]
+        );
+        assert_eq!(release.disc_to_total_tracks[&2], 3);
+    }
+
+    // Pink Floyd - Atom Heart Mother (LP): side A is a single suite, side B ends with one
+    #[test]
+    fn multi_part_vinyl_release_is_numbered_sequentially() {
+        let release = release_with_tracklist(vec![
+            heading("Side One"),
+            index(
+                "Atom Heart Mother",
+                &["A.a", "A.b", "A.c", "A.d", "A.e", "A.f"],
+            ),
+            heading("Side Two"),
+            track("B1"),
+            track("B2"),
+            track("B3"),
+            index("Alan's Psychedelic Breakfast", &["B4a", "B4b", "B4c"]),
+        ]);
+        assert_eq!(
+            disc_positions(&release),
+            (1..=12).map(|position| (1, position)).collect_vec()
+        );
+    }
+
+    #[test]
+    fn index_position_skips_side_letter() {
+        let serialized: serialized::DiscogsTrack = serde_json::from_value(track("A3a")).unwrap();
+        assert_eq!(
+            DiscogsTrack::index_disc_position(&serialized).unwrap(),
+            Some((None, 3))
+        );
+        let serialized: serialized::DiscogsTrack = serde_json::from_value(track("2-3.1")).unwrap();
+        assert_eq!(
+            DiscogsTrack::index_disc_position(&serialized).unwrap(),
+            Some((Some(2), 3))
+        );
     }
 }
@@ -1,6 +1,11 @@
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
+pub struct DiscogsIdentity {
+    pub username: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct DiscogsMaster {
     pub main_release_url: String,
     pub year: i32,
@@ -8,14 +13,32 @@ pub struct DiscogsMaster {
 
 #[derive(Serialize, Deserialize)]
 pub struct DiscogsRelease {
+    pub id: u64,
     pub title: String,
     pub uri: String,
     pub images: Option<Vec<DiscogsImage>>,
     pub tracklist: Vec<DiscogsTrack>,
     pub artists: Vec<DiscogsArtist>,
+    pub extraartists: Option<Vec<DiscogsArtist>>,
     pub year: i32,
     pub styles: Option<Vec<String>>,
     pub format_quantity: Option<u32>,
+    pub master_url: Option<String>,
+    pub formats: Option<Vec<DiscogsFormat>>,
+    pub labels: Option<Vec<DiscogsLabel>>,
+    pub country: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DiscogsLabel {
+    pub name: String,
+    pub catno: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DiscogsFormat {
+    pub name: String,
+    pub descriptions: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -23,15 +46,19 @@ pub struct DiscogsTrack {
     pub title: String,
     pub type_: String,
     pub artists: Option<Vec<DiscogsArtist>>,
+    pub extraartists: Option<Vec<DiscogsArtist>>,
     pub position: Option<String>,
     pub sub_tracks: Option<Vec<DiscogsTrack>>,
     pub duration: Option<String>,
+    pub isrc: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct DiscogsArtist {
     pub name: String,
     pub join: Option<String>,
+    pub role: Option<String>,
+    pub tracks: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -8,8 +8,15 @@ use clap::Parser;
 
 use crate::cli::{Cli, Command};
 use crate::command::add_covers::add_covers;
+use crate::command::check_token::check_token;
+use crate::command::clean_backups::clean_backups;
 use crate::command::generate_completions::generate_completions;
 use crate::command::import::import;
+use crate::command::move_files::move_files;
+use crate::discogs::matcher::DiscogsMatcher;
+use crate::util::console;
+use crate::util::console::OutputFormat;
+use crate::util::console_event::ConsoleEvent;
 use crate::util::console_styleable::ConsoleStyleable;
 
 mod cli;
@@ -24,12 +31,19 @@ fn main() -> ExitCode {
     match try_main() {
         Ok(_) => ExitCode::SUCCESS,
         Err(error) => {
-            eprintln!("{}", error.deref().error_styled());
-            error
-                .chain()
-                .skip(1)
-                .for_each(|cause| eprintln!("{} {}", "↳".error_styled(), cause.error_styled()));
-            eprintln!("\n{}", error.backtrace().error_styled());
+            if console::get().output_format() == OutputFormat::Json {
+                console::get().emit(&ConsoleEvent::Error {
+                    message: error.to_string(),
+                    causes: error.chain().skip(1).map(ToString::to_string).collect(),
+                });
+            } else {
+                eprintln!("{}", error.deref().error_styled());
+                error
+                    .chain()
+                    .skip(1)
+                    .for_each(|cause| eprintln!("{} {}", "↳".error_styled(), cause.error_styled()));
+                eprintln!("\n{}", error.backtrace().error_styled());
+            }
             ExitCode::FAILURE
         }
     }
@@ -38,10 +52,20 @@ fn main() -> ExitCode {
 fn try_main() -> Result<()> {
     let cli = Cli::parse();
 
+    console::get_mut().set_output_format(cli.output_format);
+    console::get_mut().set_verbosity(cli.verbose);
+
+    if let (true, Some(discogs_token)) = (cli.save_token, &cli.discogs_token) {
+        DiscogsMatcher::save_token(discogs_token)?;
+    }
+
     match cli.command {
         Command::GenerateCompletions(args) => generate_completions(args),
         Command::Import(args) => import(args, cli.discogs_token)?,
         Command::AddCovers(args) => add_covers(args, cli.discogs_token)?,
+        Command::Move(args) => move_files(args)?,
+        Command::CheckToken => check_token(cli.discogs_token)?,
+        Command::CleanBackups(args) => clean_backups(args)?,
     }
 
     Ok(())
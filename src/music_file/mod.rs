@@ -1,15 +1,21 @@
-use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use anyhow::Context;
 use anyhow::Result;
+use chrono::{Datelike, Local, NaiveDate};
+use clap::ValueEnum;
+use once_cell::sync::Lazy;
+use regex::Regex;
 use sanitize_filename::sanitize_with_options;
+use unidecode::unidecode;
 
+use crate::console_warn;
 use crate::tag;
 use crate::tag::frame::FrameId;
 use crate::tag::Tag;
 use crate::util::audio_file_duration::from_path;
+use crate::util::console_styleable::ConsoleStyleable;
 use crate::util::path_extensions::PathExtensions;
 
 pub struct MusicFile {
@@ -19,12 +25,39 @@ pub struct MusicFile {
 }
 
 impl MusicFile {
-    pub fn from_path(path: &Path) -> Result<Option<Self>> {
-        if let Some(tag) = tag::read_from_path(path, path.extension_or_empty())? {
+    pub fn from_path(
+        path: &Path,
+        infer_track_from_filename: bool,
+        year_from_folder: bool,
+    ) -> Result<Option<Self>> {
+        let tag = match tag::read_from_path(path, path.extension_or_empty()) {
+            Ok(tag) => tag,
+            Err(e) => {
+                console_warn!(
+                    "{} {} ({})",
+                    "Couldn't read tags, skipping:".warning_styled(),
+                    path.file_name_or_empty().path_styled(),
+                    e
+                );
+                return Ok(None);
+            }
+        };
+        if let Some(mut tag) = tag {
+            if infer_track_from_filename && tag.track_number().is_none() {
+                if let Some((disc, track)) = disc_track_from_file_name(path.file_name_or_empty()) {
+                    tag.set_track_number(Some(track));
+                    if tag.disc().is_none() {
+                        tag.set_disc(disc);
+                    }
+                }
+            }
+            if year_from_folder && tag.year().is_none() {
+                tag.set_year(year_from_folders(path));
+            }
             Ok(Some(MusicFile {
                 file_path: PathBuf::from(path),
                 tag,
-                duration: from_path(path)?,
+                duration: from_path(path).ok().flatten(),
             }))
         } else {
             Ok(None)
@@ -32,34 +65,222 @@ impl MusicFile {
     }
 }
 
-pub fn relative_path_for(tag: &dyn Tag, with_extension: &str) -> Result<PathBuf> {
-    Ok(music_folder_path_for(tag.deref())?.join(music_file_name_for(tag.deref(), with_extension)?))
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputStructure {
+    ByArtist,
+    ByLabel,
+    Mirror,
 }
 
-pub fn music_folder_path_for(tag: &dyn Tag) -> Result<PathBuf> {
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputCase {
+    Preserve,
+    Lower,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ArtistFolderFrom {
+    AlbumArtist,
+    Artist,
+}
+
+pub struct MusicPathOptions {
+    pub default_album_artist: Option<String>,
+    pub inbox_path: Option<PathBuf>,
+    pub singles_template: Option<String>,
+    pub output_structure: OutputStructure,
+    pub label_fallback_to_artist: bool,
+    pub disc_subfolder_template: Option<String>,
+    pub cover_sidecar_names: Vec<String>,
+    pub prefer_existing_cover: bool,
+    pub output_case: OutputCase,
+    pub all_covers: bool,
+    pub artist_folder_from: ArtistFolderFrom,
+    pub alpha_buckets: bool,
+    pub cover_max_bytes: Option<u64>,
+}
+
+const UNKNOWN_LABEL_FOLDER_NAME: &str = "Unknown Label";
+const MIN_PLAUSIBLE_YEAR: i32 = 1900;
+const DIGITS_BUCKET: &str = "0-9";
+const OTHER_BUCKET: &str = "#";
+pub const DEFAULT_SINGLES_TEMPLATE: &str = "{album_artist}/Singles/({year}) {album}";
+const BY_LABEL_TEMPLATE: &str = "{label}/({year}) {album_artist} - {album}";
+pub const DEFAULT_DISC_SUBFOLDER_TEMPLATE: &str = "CD{disc}";
+
+#[allow(clippy::unwrap_used)]
+static BRACKETED_YEAR_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"[(\[](\d{4})[)\]]").unwrap());
+#[allow(clippy::unwrap_used)]
+static BARE_YEAR_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\D)(\d{4})(?:\D|$)").unwrap());
+#[allow(clippy::unwrap_used)]
+static DISC_FOLDER_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^\s*(?:cd|disc|disk)\s*\d+\b").unwrap());
+
+// Label comes from the matched release as it isn't kept in tags
+pub fn relative_path_for(
+    tag: &dyn Tag,
+    with_extension: &str,
+    single: bool,
+    label: Option<&str>,
+    source_relative_path: &Path,
+    options: &MusicPathOptions,
+) -> Result<PathBuf> {
+    if options.output_structure == OutputStructure::Mirror {
+        return Ok(source_relative_path.with_extension(with_extension));
+    }
+    let mut path = music_folder_path_for(tag, single, label, options)?;
+    if let Some(disc_subfolder) = disc_subfolder_for(tag, options) {
+        path.push(disc_subfolder);
+    }
+    Ok(path.join(music_file_name_for(
+        tag,
+        with_extension,
+        options.output_case,
+    )?))
+}
+
+pub fn disc_subfolder_for(tag: &dyn Tag, options: &MusicPathOptions) -> Option<String> {
+    let template = options.disc_subfolder_template.as_deref()?;
+    let disc = tag.disc()?;
+    // Discogs tags carry disc only for multi-disc releases, other tags may have explicit total
+    if tag.total_discs().is_some_and(|v| v <= 1) {
+        return None;
+    }
+    Some(with_output_case(
+        sanitize_path(template.replace("{disc}", &disc.to_string())),
+        options.output_case,
+    ))
+}
+
+pub fn music_folder_path_for(
+    tag: &dyn Tag,
+    single: bool,
+    label: Option<&str>,
+    options: &MusicPathOptions,
+) -> Result<PathBuf> {
+    let path = music_folder_path_preserving_case_for(tag, single, label, options)?;
+    Ok(match options.output_case {
+        OutputCase::Preserve => path,
+        OutputCase::Lower => path
+            .iter()
+            .map(|component| component.to_string_lossy().to_lowercase())
+            .collect(),
+    })
+}
+
+fn music_folder_path_preserving_case_for(
+    tag: &dyn Tag,
+    single: bool,
+    label: Option<&str>,
+    options: &MusicPathOptions,
+) -> Result<PathBuf> {
     let context = |frame_id: FrameId| format!("No {} to form music folder name", frame_id);
-    let album_artist = tag
-        .album_artist()
-        .or_else(|| tag.artist())
-        .with_context(|| context(FrameId::AlbumArtist))?;
-    let year = tag.year().with_context(|| context(FrameId::Year))?;
+    // Only picks the folder, what gets written to the tag stays as is
+    let album_artist = match options.artist_folder_from {
+        ArtistFolderFrom::AlbumArtist => tag.album_artist().or_else(|| tag.artist()),
+        ArtistFolderFrom::Artist => tag.artist().or_else(|| tag.album_artist()),
+    }
+    .or(options.default_album_artist.as_deref())
+    .with_context(|| context(FrameId::AlbumArtist))?;
     let album = tag.album().with_context(|| context(FrameId::Album))?;
 
-    let mut path = PathBuf::new();
+    let mut path = options.inbox_path.clone().unwrap_or_default();
+    let alpha_bucket = Some(alpha_bucket_for(album_artist)).filter(|_| options.alpha_buckets);
+    let year = tag.year().filter(|v| *v > 0);
+    if let Some(template) = options.singles_template.as_deref().filter(|_| single) {
+        path.extend(alpha_bucket);
+        path.push(template_path(template, album_artist, year, album, label));
+        return Ok(path);
+    }
+    if options.output_structure == OutputStructure::ByLabel
+        && (label.is_some() || !options.label_fallback_to_artist)
+    {
+        path.push(template_path(
+            BY_LABEL_TEMPLATE,
+            album_artist,
+            year,
+            album,
+            label,
+        ));
+        return Ok(path);
+    }
+    path.extend(alpha_bucket);
     path.push(sanitize_path(album_artist));
-    path.push(sanitize_path(format!("({}) {}", year, album)));
+    path.push(sanitize_path(match tag.year().filter(|v| *v > 0) {
+        Some(year) => format!("({}) {}", year, album),
+        None => album.to_owned(),
+    }));
 
     Ok(path)
 }
 
-pub fn music_file_name_for(tag: &dyn Tag, with_extension: &str) -> Result<String> {
+// Placeholders left without value leave empty "()" behind, so these are dropped
+fn template_path(
+    template: &str,
+    album_artist: &str,
+    year: Option<i32>,
+    album: &str,
+    label: Option<&str>,
+) -> PathBuf {
+    let year = year.map(|v| v.to_string()).unwrap_or_default();
+    template
+        .split('/')
+        .filter(|v| !v.is_empty())
+        .map(|component| {
+            sanitize_path(
+                component
+                    .replace("{album_artist}", album_artist)
+                    .replace("{year}", &year)
+                    .replace("{album}", album)
+                    .replace("{label}", label.unwrap_or(UNKNOWN_LABEL_FOLDER_NAME))
+                    .replace("()", "")
+                    .trim(),
+            )
+        })
+        .collect()
+}
+
+// Leading article is ignored like sorting players do: "The Beatles" goes to "B"
+fn alpha_bucket_for(artist: &str) -> String {
+    let artist = artist.trim();
+    let sort_name = artist
+        .get(..4)
+        .filter(|v| v.eq_ignore_ascii_case("the "))
+        .map_or(artist, |_| &artist[4..]);
+    match unidecode(sort_name).chars().find(|v| !v.is_whitespace()) {
+        Some(c) if c.is_ascii_alphabetic() => c.to_ascii_uppercase().to_string(),
+        Some(c) if c.is_ascii_digit() => DIGITS_BUCKET.to_owned(),
+        _ => OTHER_BUCKET.to_owned(),
+    }
+}
+
+pub fn inbox_path_for(template: &str, date: NaiveDate) -> PathBuf {
+    template
+        .split('/')
+        .filter(|v| !v.is_empty())
+        .map(|component| {
+            sanitize_path(
+                component
+                    .replace("{import_year}", &date.format("%Y").to_string())
+                    .replace("{import_month}", &date.format("%m").to_string())
+                    .replace("{import_date}", &date.format("%Y-%m-%d").to_string()),
+            )
+        })
+        .collect()
+}
+
+pub fn music_file_name_for(
+    tag: &dyn Tag,
+    with_extension: &str,
+    output_case: OutputCase,
+) -> Result<String> {
     let context = |frame_id: FrameId| format!("No {} to form music file name", frame_id);
     let track = tag
         .track_number()
         .with_context(|| context(FrameId::Track))?;
     let title = tag.title().with_context(|| context(FrameId::Title))?;
 
-    Ok(sanitize_path(match tag.disc() {
+    let file_name = sanitize_path(match tag.disc() {
         Some(disc) => format!(
             "{disc:02}.{track:02}. {title}.{extension}",
             disc = disc,
@@ -73,7 +294,54 @@ pub fn music_file_name_for(tag: &dyn Tag, with_extension: &str) -> Result<String
             title = title,
             extension = with_extension,
         ),
-    }))
+    });
+
+    Ok(with_output_case(file_name, output_case))
+}
+
+// Case-insensitive filesystems would otherwise end up with "Artist" and "artist" folders
+fn with_output_case(value: String, output_case: OutputCase) -> String {
+    match output_case {
+        OutputCase::Preserve => value,
+        OutputCase::Lower => value.to_lowercase(),
+    }
+}
+
+// Accepts names like "03 - Title.mp3", "03. Title.mp3" or "01.03. Title.mp3" as written by this tool
+fn disc_track_from_file_name(file_name: &str) -> Option<(Option<u32>, u32)> {
+    #[allow(clippy::unwrap_used)]
+    let regex = Regex::new(r"^(?:(\d{1,2})\.)?(\d{1,3})(?:\.|\s*-|\s)").unwrap();
+    let captures = regex.captures(file_name)?;
+    let disc = captures.get(1).and_then(|v| v.as_str().parse::<u32>().ok());
+    let track = captures.get(2)?.as_str().parse::<u32>().ok()?;
+    Some((disc, track))
+}
+
+// Disc subfolders like "Album (1997)/CD1" rarely carry the year, so look up to the album folder
+fn year_from_folders(path: &Path) -> Option<i32> {
+    for folder in path.ancestors().skip(1) {
+        let folder_name = folder.file_name_or_empty();
+        if let Some(year) = year_from_folder_name(folder_name) {
+            return Some(year);
+        }
+        if !DISC_FOLDER_REGEX.is_match(folder_name) {
+            break;
+        }
+    }
+    None
+}
+
+// Accepts names like "Artist - (1997) Album" or "Album [1997]", bracketed years win over bare ones
+fn year_from_folder_name(folder_name: &str) -> Option<i32> {
+    let plausible_years = MIN_PLAUSIBLE_YEAR..=Local::now().year() + 1;
+    [&BRACKETED_YEAR_REGEX, &BARE_YEAR_REGEX]
+        .iter()
+        .find_map(|regex| {
+            regex
+                .captures_iter(folder_name)
+                .filter_map(|v| v.get(1)?.as_str().parse::<i32>().ok())
+                .find(|v| plausible_years.contains(v))
+        })
 }
 
 fn sanitize_path<S: AsRef<str>>(name: S) -> String {
@@ -85,3 +353,59 @@ fn sanitize_path<S: AsRef<str>>(name: S) -> String {
         },
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn by_label_template_places_label_first() {
+        assert_eq!(
+            template_path(
+                BY_LABEL_TEMPLATE,
+                "Artist",
+                Some(1997),
+                "Album",
+                Some("Warp")
+            ),
+            PathBuf::from("Warp/(1997) Artist - Album")
+        );
+        assert_eq!(
+            template_path(BY_LABEL_TEMPLATE, "Artist", None, "Album", None),
+            PathBuf::from("Unknown Label/Artist - Album")
+        );
+    }
+
+    #[test]
+    fn singles_template_drops_missing_year() {
+        assert_eq!(
+            template_path(DEFAULT_SINGLES_TEMPLATE, "Artist", None, "Song", None),
+            PathBuf::from("Artist/Singles/Song")
+        );
+    }
+
+    #[test]
+    fn year_is_parsed_from_folder_name() {
+        assert_eq!(year_from_folder_name("Artist - (1997) Album"), Some(1997));
+        assert_eq!(year_from_folder_name("Album [2001]"), Some(2001));
+        assert_eq!(year_from_folder_name("1999 - Album"), Some(1999));
+        assert_eq!(year_from_folder_name("Album 3000 (1985)"), Some(1985));
+        assert_eq!(year_from_folder_name("Album 1234"), None);
+    }
+
+    #[test]
+    fn year_is_looked_up_above_disc_folders() {
+        assert_eq!(
+            year_from_folders(Path::new("/music/Album (1997)/CD1/01.mp3")),
+            Some(1997)
+        );
+        assert_eq!(
+            year_from_folders(Path::new("/music/Album (1997)/Disc 2 - Bonus/01.mp3")),
+            Some(1997)
+        );
+        assert_eq!(
+            year_from_folders(Path::new("/music/2020 rips/Album/01.mp3")),
+            None
+        );
+    }
+}
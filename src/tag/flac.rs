@@ -14,7 +14,12 @@ impl Tag for metaflac::Tag {
                 FLAC_TRACK => FrameId::Track,
                 FLAC_TOTAL_TRACKS => FrameId::TotalTracks,
                 FLAC_DISC => FrameId::Disc,
+                FLAC_TOTAL_DISCS | FLAC_TOTAL_DISCS_ALT => FrameId::TotalDiscs,
                 FLAC_GENRE => FrameId::Genre,
+                FLAC_ENCODED_BY => FrameId::EncodedBy,
+                FLAC_ISRC => FrameId::Isrc,
+                FLAC_ALBUM_SORT => FrameId::AlbumSort,
+                FLAC_TITLE_SORT => FrameId::TitleSort,
                 key => FrameId::CustomText {
                     key: key.to_owned(),
                 },
@@ -73,6 +78,13 @@ impl Tag for metaflac::Tag {
             .map(|v| v.as_str())
     }
 
+    fn artists(&self) -> Vec<&str> {
+        metaflac::Tag::vorbis_comments(self)
+            .and_then(|v| v.artist())
+            .map(|v| v.iter().map(|v| v.as_str()).collect())
+            .unwrap_or_default()
+    }
+
     fn set_artist(&mut self, artist: Option<String>) {
         let comments = metaflac::Tag::vorbis_comments_mut(self);
         if let Some(artist) = artist {
@@ -157,12 +169,22 @@ impl Tag for metaflac::Tag {
     }
 
     fn total_discs(&self) -> Option<u32> {
-        // no-op
-        None
+        metaflac::Tag::vorbis_comments(self).and_then(|v| {
+            [FLAC_TOTAL_DISCS, FLAC_TOTAL_DISCS_ALT]
+                .iter()
+                .find_map(|key| v.get(key)?.first()?.parse::<u32>().ok())
+                .or_else(|| vorbis_comment_as_pair(v, FLAC_DISC)?.1)
+        })
     }
 
-    fn set_total_discs(&mut self, _total_discs: Option<u32>) {
-        // no-op
+    fn set_total_discs(&mut self, total_discs: Option<u32>) {
+        let comments = metaflac::Tag::vorbis_comments_mut(self);
+        comments.remove(FLAC_TOTAL_DISCS_ALT);
+        if let Some(total_discs) = total_discs {
+            comments.set(FLAC_TOTAL_DISCS, vec![format!("{}", total_discs)]);
+        } else {
+            comments.remove(FLAC_TOTAL_DISCS)
+        }
     }
 
     fn genre(&self) -> Option<&str> {
@@ -180,12 +202,79 @@ impl Tag for metaflac::Tag {
         }
     }
 
-    fn custom_text(&self, key: &str) -> Option<&str> {
+    fn set_genres(&mut self, genres: Vec<String>) {
+        let comments = metaflac::Tag::vorbis_comments_mut(self);
+        if genres.is_empty() {
+            comments.remove_genre()
+        } else {
+            comments.set_genre(genres);
+        }
+    }
+
+    fn encoded_by(&self) -> Option<&str> {
         metaflac::Tag::vorbis_comments(self)
-            .and_then(|v| v.get(key).and_then(|v| v.iter().next()))
+            .and_then(|v| v.get(FLAC_ENCODED_BY).and_then(|v| v.iter().next()))
             .map(|v| v.as_str())
     }
 
+    fn set_encoded_by(&mut self, encoded_by: Option<String>) {
+        let comments = metaflac::Tag::vorbis_comments_mut(self);
+        if let Some(encoded_by) = encoded_by {
+            comments.set(FLAC_ENCODED_BY, vec![encoded_by]);
+        } else {
+            comments.remove(FLAC_ENCODED_BY)
+        }
+    }
+
+    fn isrc(&self) -> Option<&str> {
+        metaflac::Tag::vorbis_comments(self)
+            .and_then(|v| v.get(FLAC_ISRC).and_then(|v| v.iter().next()))
+            .map(|v| v.as_str())
+    }
+
+    fn set_isrc(&mut self, isrc: Option<String>) {
+        let comments = metaflac::Tag::vorbis_comments_mut(self);
+        if let Some(isrc) = isrc {
+            comments.set(FLAC_ISRC, vec![isrc]);
+        } else {
+            comments.remove(FLAC_ISRC)
+        }
+    }
+
+    fn album_sort(&self) -> Option<&str> {
+        metaflac::Tag::vorbis_comments(self)
+            .and_then(|v| v.get(FLAC_ALBUM_SORT).and_then(|v| v.iter().next()))
+            .map(|v| v.as_str())
+    }
+
+    fn set_album_sort(&mut self, album_sort: Option<String>) {
+        let comments = metaflac::Tag::vorbis_comments_mut(self);
+        if let Some(album_sort) = album_sort {
+            comments.set(FLAC_ALBUM_SORT, vec![album_sort]);
+        } else {
+            comments.remove(FLAC_ALBUM_SORT)
+        }
+    }
+
+    fn title_sort(&self) -> Option<&str> {
+        metaflac::Tag::vorbis_comments(self)
+            .and_then(|v| v.get(FLAC_TITLE_SORT).and_then(|v| v.iter().next()))
+            .map(|v| v.as_str())
+    }
+
+    fn set_title_sort(&mut self, title_sort: Option<String>) {
+        let comments = metaflac::Tag::vorbis_comments_mut(self);
+        if let Some(title_sort) = title_sort {
+            comments.set(FLAC_TITLE_SORT, vec![title_sort]);
+        } else {
+            comments.remove(FLAC_TITLE_SORT)
+        }
+    }
+
+    fn custom_text(&self, key: &str) -> Option<&str> {
+        self.custom_texts(key).into_iter().next()
+    }
+
     fn set_custom_text(&mut self, key: String, value: Option<String>) {
         let comments = metaflac::Tag::vorbis_comments_mut(self);
         if let Some(value) = value {
@@ -195,6 +284,66 @@ impl Tag for metaflac::Tag {
         }
     }
 
+    fn custom_texts(&self, key: &str) -> Vec<&str> {
+        metaflac::Tag::vorbis_comments(self)
+            .and_then(|v| v.get(key))
+            .map(|v| v.iter().map(|v| v.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    fn add_custom_text(&mut self, key: String, value: String) {
+        let comments = metaflac::Tag::vorbis_comments_mut(self);
+        let mut values = comments.get(&key).cloned().unwrap_or_default();
+        values.push(value);
+        comments.set(key, values);
+    }
+
+    fn set_lyrics(&mut self, lyrics: Option<String>) {
+        let comments = metaflac::Tag::vorbis_comments_mut(self);
+        if let Some(lyrics) = lyrics {
+            comments.set_lyrics(vec![lyrics]);
+        } else {
+            comments.remove_lyrics()
+        }
+    }
+
+    fn set_front_cover(&mut self, picture: Option<Picture>) {
+        metaflac::Tag::remove_picture_type(self, metaflac::block::PictureType::CoverFront);
+        if let Some(picture) = picture {
+            metaflac::Tag::add_picture(
+                self,
+                picture.mime_type,
+                metaflac::block::PictureType::CoverFront,
+                picture.data,
+            );
+        }
+    }
+
+    fn pictures(&self) -> Vec<Picture> {
+        metaflac::Tag::pictures(self)
+            .map(|picture| Picture {
+                picture_type: match picture.picture_type {
+                    metaflac::block::PictureType::CoverFront => PictureType::Front,
+                    metaflac::block::PictureType::CoverBack => PictureType::Back,
+                    metaflac::block::PictureType::Leaflet => PictureType::Booklet,
+                    _ => PictureType::Other,
+                },
+                mime_type: picture.mime_type.clone(),
+                data: picture.data.clone(),
+            })
+            .collect()
+    }
+
+    fn add_picture(&mut self, picture_type: PictureType, mime_type: String, data: Vec<u8>) {
+        let picture_type = match picture_type {
+            PictureType::Front => metaflac::block::PictureType::CoverFront,
+            PictureType::Back => metaflac::block::PictureType::CoverBack,
+            PictureType::Booklet => metaflac::block::PictureType::Leaflet,
+            PictureType::Other => metaflac::block::PictureType::Other,
+        };
+        metaflac::Tag::add_picture(self, mime_type, picture_type, data);
+    }
+
     fn clear(&mut self) {
         #![allow(clippy::unwrap_used)] // FIXME: Should deal with absence of media info
         let stream_info = metaflac::Tag::get_streaminfo(self).unwrap().to_owned();
@@ -209,18 +358,29 @@ impl Tag for metaflac::Tag {
         file.rewind()?;
         file.set_len(0)?;
 
-        file.write_all(b"fLaC")?;
+        write_with_data(self, file, &data)
+    }
 
-        let blocks: Vec<&metaflac::Block> = self.blocks().collect();
-        let blocks_count = blocks.len();
-        for (i, block) in blocks.iter().enumerate() {
-            block.write_to(i == blocks_count - 1, file)?;
-        }
+    fn write_to_vec(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let data = metaflac::Tag::skip_metadata(&mut Cursor::new(data));
+        let mut result = Vec::new();
+        write_with_data(self, &mut result, &data)?;
+        Ok(result)
+    }
+}
 
-        file.write_all(&data[..])?;
+fn write_with_data(tag: &metaflac::Tag, writer: &mut impl Write, data: &[u8]) -> Result<()> {
+    writer.write_all(b"fLaC")?;
 
-        Ok(())
+    let blocks: Vec<&metaflac::Block> = tag.blocks().collect();
+    let blocks_count = blocks.len();
+    for (i, block) in blocks.iter().enumerate() {
+        block.write_to(i == blocks_count - 1, writer)?;
     }
+
+    writer.write_all(data)?;
+
+    Ok(())
 }
 
 fn vorbis_comment_as_pair(
@@ -242,4 +402,10 @@ const FLAC_YEAR: &str = "DATE";
 const FLAC_TRACK: &str = "TRACKNUMBER";
 const FLAC_TOTAL_TRACKS: &str = "TOTALTRACKS";
 const FLAC_DISC: &str = "DISCNUMBER";
+const FLAC_TOTAL_DISCS: &str = "DISCTOTAL";
+const FLAC_TOTAL_DISCS_ALT: &str = "TOTALDISCS";
 const FLAC_GENRE: &str = "GENRE";
+const FLAC_ENCODED_BY: &str = "ENCODEDBY";
+const FLAC_ISRC: &str = "ISRC";
+const FLAC_ALBUM_SORT: &str = "ALBUMSORT";
+const FLAC_TITLE_SORT: &str = "TITLESORT";
@@ -3,18 +3,52 @@ use std::str::FromStr;
 
 use anyhow::{bail, Result};
 
-pub enum FrameId {
-    Title,
-    Album,
-    AlbumArtist,
-    Artist,
-    Year,
-    Track,
-    TotalTracks,
-    Disc,
-    TotalDiscs,
-    Genre,
-    CustomText { key: String },
+// Single list of standard frames so the enum, its names and STANDARD can't drift apart
+macro_rules! standard_frame_ids {
+    ($($variant:ident => $name:literal,)*) => {
+        pub enum FrameId {
+            $($variant,)*
+            CustomText { key: String },
+        }
+
+        impl FrameId {
+            pub const STANDARD: &'static [FrameId] = &[$(FrameId::$variant,)*];
+
+            fn standard_name(&self) -> Option<&'static str> {
+                match self {
+                    $(FrameId::$variant => Some($name),)*
+                    FrameId::CustomText { .. } => None,
+                }
+            }
+
+            fn from_standard_name(name: &str) -> Option<FrameId> {
+                match name {
+                    $($name => Some(FrameId::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+// Custom frames must be spelled out so that a typo in a standard name is not silently accepted
+const CUSTOM_FRAME_PREFIXES: [&str; 2] = ["TXXX:", "custom:"];
+
+standard_frame_ids! {
+    Title => "Title",
+    Album => "Album",
+    AlbumArtist => "Album Artist",
+    Artist => "Artist",
+    Year => "Year",
+    Track => "Track",
+    TotalTracks => "Total Tracks",
+    Disc => "Disc",
+    TotalDiscs => "Total Discs",
+    Genre => "Genre",
+    EncodedBy => "Encoded By",
+    Isrc => "ISRC",
+    AlbumSort => "Album Sort",
+    TitleSort => "Title Sort",
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -49,23 +83,10 @@ impl FrameContent {
 
 impl Display for FrameId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                FrameId::Title => "Title",
-                FrameId::Album => "Album",
-                FrameId::AlbumArtist => "Album Artist",
-                FrameId::Artist => "Artist",
-                FrameId::Year => "Year",
-                FrameId::Track => "Track",
-                FrameId::TotalTracks => "Total Tracks",
-                FrameId::Disc => "Disc",
-                FrameId::TotalDiscs => "Total Discs",
-                FrameId::Genre => "Genre",
-                FrameId::CustomText { key } => key,
-            }
-        )
+        match self {
+            FrameId::CustomText { key } => write!(f, "{}{}", CUSTOM_FRAME_PREFIXES[0], key),
+            standard => write!(f, "{}", standard.standard_name().unwrap_or_default()),
+        }
     }
 }
 
@@ -73,30 +94,81 @@ impl FromStr for FrameId {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        Ok(match s {
-            "Title" => FrameId::Title,
-            "Album" => FrameId::Album,
-            "Album Artist" => FrameId::AlbumArtist,
-            "Artist" => FrameId::Artist,
-            "Year" => FrameId::Year,
-            "Track" => FrameId::Track,
-            "Total Tracks" => FrameId::TotalTracks,
-            "Disc" => FrameId::Disc,
-            "Total Discs" => FrameId::TotalDiscs,
-            "Genre" => FrameId::Genre,
-            key => FrameId::CustomText {
+        if let Some(frame_id) = FrameId::from_standard_name(s) {
+            return Ok(frame_id);
+        }
+        match FrameId::custom_key(s) {
+            Some(key) if !key.is_empty() => Ok(FrameId::CustomText {
                 key: key.to_owned(),
-            },
+            }),
+            _ => bail!(
+                "Unknown frame \"{}\", custom frames need \"{}\" or \"{}\" prefix",
+                s,
+                CUSTOM_FRAME_PREFIXES[0],
+                CUSTOM_FRAME_PREFIXES[1]
+            ),
+        }
+    }
+}
+
+impl FrameId {
+    // Key of a custom frame name like "TXXX:CATALOGNUMBER", prefix case is not significant
+    pub fn custom_key(name: &str) -> Option<&str> {
+        CUSTOM_FRAME_PREFIXES.iter().find_map(|prefix| {
+            name.get(..prefix.len())
+                .filter(|v| v.eq_ignore_ascii_case(prefix))
+                .map(|_| &name[prefix.len()..])
         })
     }
 }
 
-impl ToString for FrameContent {
-    fn to_string(&self) -> String {
+impl Display for FrameContent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            FrameContent::Str(v) => v.to_owned(),
-            FrameContent::I32(v) => v.to_string(),
-            FrameContent::U32(v) => v.to_string(),
+            FrameContent::Str(v) => write!(f, "{v}"),
+            FrameContent::I32(v) => write!(f, "{v}"),
+            FrameContent::U32(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_frames_round_trip_through_names() {
+        for frame_id in FrameId::STANDARD {
+            let name = frame_id.to_string();
+            let parsed = name.parse::<FrameId>().unwrap();
+            assert!(
+                parsed.standard_name().is_some(),
+                "{} parsed as custom",
+                name
+            );
+            assert_eq!(parsed.to_string(), name);
         }
     }
+
+    #[test]
+    fn custom_frames_require_prefix() {
+        for name in ["TXXX:CATALOG", "custom:CATALOG", "txxx:CATALOG"] {
+            let parsed = name.parse::<FrameId>().unwrap();
+            assert!(matches!(parsed, FrameId::CustomText { key } if key == "CATALOG"));
+        }
+        assert!("CATALOG".parse::<FrameId>().is_err());
+        assert!("Titel".parse::<FrameId>().is_err());
+        assert!("TXXX:".parse::<FrameId>().is_err());
+    }
+
+    #[test]
+    fn custom_frames_round_trip_through_names() {
+        let frame_id = FrameId::CustomText {
+            key: "DISCOGS_RELEASE_ID".to_owned(),
+        };
+        let name = frame_id.to_string();
+        assert_eq!(name, "TXXX:DISCOGS_RELEASE_ID");
+        assert_eq!(name.parse::<FrameId>().unwrap().to_string(), name);
+    }
 }
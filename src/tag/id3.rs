@@ -1,3 +1,5 @@
+use std::io::SeekFrom;
+
 use ::id3;
 use anyhow::Result;
 use itertools::Itertools;
@@ -7,7 +9,6 @@ use super::*;
 impl Tag for id3::Tag {
     fn frame_ids(&self) -> Vec<FrameId> {
         id3::Tag::frames(self)
-            .into_iter()
             .flat_map(|frame| match frame.id() {
                 "TIT2" => vec![FrameId::Title],
                 "TALB" => vec![FrameId::Album],
@@ -17,6 +18,20 @@ impl Tag for id3::Tag {
                 "TRCK" => vec![FrameId::Track, FrameId::TotalTracks],
                 "TPOS" => vec![FrameId::Disc, FrameId::TotalDiscs],
                 "TCON" => vec![FrameId::Genre],
+                "TENC" => vec![FrameId::EncodedBy],
+                "TSRC" => vec![FrameId::Isrc],
+                "TSOA" => vec![FrameId::AlbumSort],
+                "TSOT" => vec![FrameId::TitleSort],
+                "UFID"
+                    if frame
+                        .content()
+                        .unique_file_identifier()
+                        .is_some_and(|v| v.owner_identifier == MUSICBRAINZ_UFID_OWNER) =>
+                {
+                    vec![FrameId::CustomText {
+                        key: MUSICBRAINZ_TRACK_ID.to_owned(),
+                    }]
+                }
                 "TXXX" => frame
                     .content()
                     .extended_text()
@@ -70,6 +85,13 @@ impl Tag for id3::Tag {
         id3::TagLike::artist(self)
     }
 
+    fn artists(&self) -> Vec<&str> {
+        // ID3v2.4 separates multiple values with null character
+        id3::TagLike::artist(self)
+            .map(|v| v.split('\0').filter(|v| !v.is_empty()).collect_vec())
+            .unwrap_or_default()
+    }
+
     fn set_artist(&mut self, artist: Option<String>) {
         if let Some(artist) = artist {
             id3::TagLike::set_artist(self, artist)
@@ -162,13 +184,83 @@ impl Tag for id3::Tag {
         }
     }
 
+    fn set_genres(&mut self, genres: Vec<String>) {
+        if genres.is_empty() {
+            id3::TagLike::remove_genre(self)
+        } else {
+            id3::TagLike::set_text_values(self, "TCON", genres)
+        }
+    }
+
+    fn encoded_by(&self) -> Option<&str> {
+        id3::TagLike::get(self, "TENC").and_then(|frame| frame.content().text())
+    }
+
+    fn set_encoded_by(&mut self, encoded_by: Option<String>) {
+        if let Some(encoded_by) = encoded_by {
+            id3::TagLike::set_text(self, "TENC", encoded_by)
+        } else {
+            id3::TagLike::remove(self, "TENC");
+        }
+    }
+
+    fn isrc(&self) -> Option<&str> {
+        id3::TagLike::get(self, "TSRC").and_then(|frame| frame.content().text())
+    }
+
+    fn set_isrc(&mut self, isrc: Option<String>) {
+        if let Some(isrc) = isrc {
+            id3::TagLike::set_text(self, "TSRC", isrc)
+        } else {
+            id3::TagLike::remove(self, "TSRC");
+        }
+    }
+
+    fn album_sort(&self) -> Option<&str> {
+        id3::TagLike::get(self, "TSOA").and_then(|frame| frame.content().text())
+    }
+
+    fn set_album_sort(&mut self, album_sort: Option<String>) {
+        if let Some(album_sort) = album_sort {
+            id3::TagLike::set_text(self, "TSOA", album_sort)
+        } else {
+            id3::TagLike::remove(self, "TSOA");
+        }
+    }
+
+    fn title_sort(&self) -> Option<&str> {
+        id3::TagLike::get(self, "TSOT").and_then(|frame| frame.content().text())
+    }
+
+    fn set_title_sort(&mut self, title_sort: Option<String>) {
+        if let Some(title_sort) = title_sort {
+            id3::TagLike::set_text(self, "TSOT", title_sort)
+        } else {
+            id3::TagLike::remove(self, "TSOT");
+        }
+    }
+
     fn custom_text(&self, key: &str) -> Option<&str> {
-        id3::Tag::extended_texts(self)
-            .find(|v| v.description == key)
-            .map(|v| v.value.as_str())
+        self.custom_texts(key).into_iter().next()
     }
 
     fn set_custom_text(&mut self, key: String, value: Option<String>) {
+        if key == MUSICBRAINZ_TRACK_ID {
+            id3::TagLike::remove_unique_file_identifier_by_owner_identifier(
+                self,
+                MUSICBRAINZ_UFID_OWNER,
+            );
+            if let Some(value) = value {
+                id3::TagLike::add_frame(
+                    self,
+                    id3::frame::UniqueFileIdentifier {
+                        owner_identifier: MUSICBRAINZ_UFID_OWNER.to_owned(),
+                        identifier: value.into_bytes(),
+                    },
+                );
+            }
+            return;
+        }
         if let Some(value) = value {
             id3::TagLike::add_frame(
                 self,
@@ -182,17 +274,179 @@ impl Tag for id3::Tag {
         }
     }
 
+    // ID3v2.4 keeps multiple values of a frame separated by null characters
+    fn custom_texts(&self, key: &str) -> Vec<&str> {
+        if key == MUSICBRAINZ_TRACK_ID {
+            return id3::Tag::unique_file_identifiers(self)
+                .filter(|v| v.owner_identifier == MUSICBRAINZ_UFID_OWNER)
+                .filter_map(|v| std::str::from_utf8(&v.identifier).ok())
+                .collect_vec();
+        }
+        id3::Tag::extended_texts(self)
+            .find(|v| v.description == key)
+            .map(|v| v.value.split('\0').collect_vec())
+            .unwrap_or_default()
+    }
+
+    fn add_custom_text(&mut self, key: String, value: String) {
+        let value = match self.custom_texts(&key)[..] {
+            [] => value,
+            ref values => format!("{}\0{}", values.join("\0"), value),
+        };
+        self.set_custom_text(key, Some(value));
+    }
+
+    fn set_lyrics(&mut self, lyrics: Option<String>) {
+        id3::TagLike::remove_all_lyrics(self);
+        if let Some(lyrics) = lyrics {
+            id3::TagLike::add_frame(
+                self,
+                id3::frame::Lyrics {
+                    lang: "eng".to_owned(),
+                    description: String::new(),
+                    text: lyrics,
+                },
+            );
+        }
+    }
+
+    fn set_front_cover(&mut self, picture: Option<Picture>) {
+        id3::TagLike::remove_picture_by_type(self, id3::frame::PictureType::CoverFront);
+        if let Some(picture) = picture {
+            id3::TagLike::add_frame(
+                self,
+                id3::frame::Picture {
+                    mime_type: picture.mime_type,
+                    picture_type: id3::frame::PictureType::CoverFront,
+                    description: String::new(),
+                    data: picture.data,
+                },
+            );
+        }
+    }
+
+    fn pictures(&self) -> Vec<Picture> {
+        id3::Tag::pictures(self)
+            .map(|picture| Picture {
+                picture_type: match picture.picture_type {
+                    id3::frame::PictureType::CoverFront => PictureType::Front,
+                    id3::frame::PictureType::CoverBack => PictureType::Back,
+                    id3::frame::PictureType::Leaflet => PictureType::Booklet,
+                    _ => PictureType::Other,
+                },
+                mime_type: picture.mime_type.clone(),
+                data: picture.data.clone(),
+            })
+            .collect_vec()
+    }
+
+    fn add_picture(&mut self, picture_type: PictureType, mime_type: String, data: Vec<u8>) {
+        let picture_type = match picture_type {
+            PictureType::Front => id3::frame::PictureType::CoverFront,
+            PictureType::Back => id3::frame::PictureType::CoverBack,
+            PictureType::Booklet => id3::frame::PictureType::Leaflet,
+            PictureType::Other => id3::frame::PictureType::Other,
+        };
+        // Adding a frame would replace the picture of the same type, booklet has many pages
+        let same_type_count = id3::Tag::pictures(self)
+            .filter(|v| v.picture_type == picture_type)
+            .count();
+        id3::TagLike::frames_vec_mut(self).push(
+            id3::frame::Picture {
+                mime_type,
+                picture_type,
+                description: if same_type_count > 0 {
+                    same_type_count.to_string()
+                } else {
+                    String::new()
+                },
+                data,
+            }
+            .into(),
+        );
+    }
+
     fn clear(&mut self) {
         id3::TagLike::frames_vec_mut(self).clear();
     }
 
     fn write_to(&self, file: &mut File) -> Result<()> {
-        file.rewind()?;
-        id3::v1::Tag::remove(file)?;
-        file.rewind()?;
-        id3::Encoder::new()
-            .version(id3::Version::Id3v24)
-            .encode_to_file(self, file)?;
-        Ok(())
+        write_to_storage(self, file)
+    }
+
+    fn write_to_vec(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut cursor = Cursor::new(data.to_vec());
+        write_to_storage(self, &mut cursor)?;
+        Ok(cursor.into_inner())
+    }
+
+    fn write_id3v1_to(&self, file: &mut File) -> Result<bool> {
+        let mut truncated = false;
+        let mut data = Vec::with_capacity(ID3V1_LEN);
+        data.extend_from_slice(b"TAG");
+        for text in [Tag::title(self), Tag::artist(self), Tag::album(self)] {
+            truncated |= push_id3v1_text(&mut data, text.unwrap_or_default(), ID3V1_TEXT_MAX_LEN);
+        }
+        let year = Tag::year(self).map(|v| v.to_string()).unwrap_or_default();
+        push_id3v1_text(&mut data, &year, 4);
+        // ID3v1.1 gives up the last two comment bytes to fit the track number
+        push_id3v1_text(&mut data, "", 28);
+        data.push(0);
+        data.push(
+            Tag::track_number(self)
+                .and_then(|v| u8::try_from(v).ok())
+                .unwrap_or(0),
+        );
+        data.push(id3v1_genre_id(Tag::genre(self)));
+
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&data)?;
+        Ok(truncated)
     }
 }
+
+const ID3V1_LEN: usize = 128;
+const ID3V1_NO_GENRE: u8 = 255;
+// Picard keeps the recording ID in UFID instead of TXXX, it is exposed under Picard's TXXX name
+const MUSICBRAINZ_TRACK_ID: &str = "MusicBrainz Track Id";
+const MUSICBRAINZ_UFID_OWNER: &str = "http://musicbrainz.org";
+
+// ID3v1 is Latin-1 only so everything else is transliterated to ASCII
+fn write_to_storage(tag: &id3::Tag, mut storage: impl id3::StorageFile) -> Result<()> {
+    storage.rewind()?;
+    id3::v1::Tag::remove_from_file(&mut storage)?;
+    storage.rewind()?;
+    id3::Encoder::new()
+        .version(id3::Version::Id3v24)
+        .write_to_file(tag, &mut storage)?;
+    Ok(())
+}
+
+fn push_id3v1_text(data: &mut Vec<u8>, text: &str, len: usize) -> bool {
+    let bytes = text
+        .chars()
+        .flat_map(|c| match u8::try_from(u32::from(c)) {
+            Ok(byte) => vec![byte],
+            Err(_) => unidecode::unidecode_char(c).bytes().collect(),
+        })
+        .collect::<Vec<_>>();
+    let truncated = bytes.len() > len;
+    data.extend(bytes.iter().take(len));
+    data.resize(data.len() + len.saturating_sub(bytes.len()), 0);
+    truncated
+}
+
+fn id3v1_genre_id(genre: Option<&str>) -> u8 {
+    let Some(genre) = genre else {
+        return ID3V1_NO_GENRE;
+    };
+    (0..ID3V1_NO_GENRE)
+        .find(|&genre_id| {
+            let tag = id3::v1::Tag {
+                genre_id,
+                ..id3::v1::Tag::new()
+            };
+            tag.genre().is_some_and(|v| v.eq_ignore_ascii_case(genre))
+        })
+        .unwrap_or(ID3V1_NO_GENRE)
+}
@@ -20,8 +20,15 @@ impl Tag for mp4ameta::Tag {
                 mp4ameta::DataIdent::Fourcc(mp4ameta::ident::CUSTOM_GENRE) => {
                     Some(vec![FrameId::Genre])
                 }
+                mp4ameta::DataIdent::Fourcc(mp4ameta::ident::ENCODER) => {
+                    Some(vec![FrameId::EncodedBy])
+                }
+                mp4ameta::DataIdent::Fourcc(M4A_ALBUM_SORT_ORDER) => Some(vec![FrameId::AlbumSort]),
+                mp4ameta::DataIdent::Fourcc(M4A_TITLE_SORT_ORDER) => Some(vec![FrameId::TitleSort]),
                 mp4ameta::DataIdent::Freeform { name, .. } => {
-                    if data.is_string() {
+                    if data.is_string() && name == M4A_ISRC {
+                        Some(vec![FrameId::Isrc])
+                    } else if data.is_string() {
                         Some(vec![FrameId::CustomText {
                             key: name.to_owned(),
                         }])
@@ -75,6 +82,10 @@ impl Tag for mp4ameta::Tag {
         mp4ameta::Tag::artist(self)
     }
 
+    fn artists(&self) -> Vec<&str> {
+        mp4ameta::Tag::artists(self).collect()
+    }
+
     fn set_artist(&mut self, artist: Option<String>) {
         if let Some(artist) = artist {
             mp4ameta::Tag::set_artist(self, artist)
@@ -155,6 +166,50 @@ impl Tag for mp4ameta::Tag {
         }
     }
 
+    fn encoded_by(&self) -> Option<&str> {
+        mp4ameta::Tag::encoder(self)
+    }
+
+    fn set_encoded_by(&mut self, encoded_by: Option<String>) {
+        if let Some(encoded_by) = encoded_by {
+            mp4ameta::Tag::set_encoder(self, encoded_by)
+        } else {
+            mp4ameta::Tag::remove_encoder(self)
+        }
+    }
+
+    fn isrc(&self) -> Option<&str> {
+        Tag::custom_text(self, M4A_ISRC)
+    }
+
+    fn set_isrc(&mut self, isrc: Option<String>) {
+        Tag::set_custom_text(self, M4A_ISRC.to_owned(), isrc)
+    }
+
+    fn album_sort(&self) -> Option<&str> {
+        mp4ameta::Tag::strings_of(self, &M4A_ALBUM_SORT_ORDER).next()
+    }
+
+    fn set_album_sort(&mut self, album_sort: Option<String>) {
+        if let Some(album_sort) = album_sort {
+            mp4ameta::Tag::set_data(self, M4A_ALBUM_SORT_ORDER, mp4ameta::Data::Utf8(album_sort))
+        } else {
+            mp4ameta::Tag::remove_data_of(self, &M4A_ALBUM_SORT_ORDER)
+        }
+    }
+
+    fn title_sort(&self) -> Option<&str> {
+        mp4ameta::Tag::strings_of(self, &M4A_TITLE_SORT_ORDER).next()
+    }
+
+    fn set_title_sort(&mut self, title_sort: Option<String>) {
+        if let Some(title_sort) = title_sort {
+            mp4ameta::Tag::set_data(self, M4A_TITLE_SORT_ORDER, mp4ameta::Data::Utf8(title_sort))
+        } else {
+            mp4ameta::Tag::remove_data_of(self, &M4A_TITLE_SORT_ORDER)
+        }
+    }
+
     fn custom_text(&self, key: &str) -> Option<&str> {
         let ident =
             mp4ameta::DataIdent::from(mp4ameta::FreeformIdent::new("com.apple.iTunes", key));
@@ -172,6 +227,53 @@ impl Tag for mp4ameta::Tag {
         }
     }
 
+    fn set_lyrics(&mut self, lyrics: Option<String>) {
+        if let Some(lyrics) = lyrics {
+            mp4ameta::Tag::set_lyrics(self, lyrics)
+        } else {
+            mp4ameta::Tag::remove_lyrics(self)
+        }
+    }
+
+    fn set_front_cover(&mut self, picture: Option<Picture>) {
+        if let Some(picture) = picture {
+            let format = match picture.mime_type.as_str() {
+                "image/png" => mp4ameta::ImgFmt::Png,
+                "image/bmp" => mp4ameta::ImgFmt::Bmp,
+                _ => mp4ameta::ImgFmt::Jpeg,
+            };
+            mp4ameta::Tag::set_artwork(self, mp4ameta::Img::new(format, picture.data))
+        } else {
+            mp4ameta::Tag::remove_artworks(self)
+        }
+    }
+
+    // MP4 artworks carry no type so all of them are taken for the front cover
+    fn pictures(&self) -> Vec<Picture> {
+        mp4ameta::Tag::artworks(self)
+            .map(|artwork| Picture {
+                picture_type: PictureType::Front,
+                mime_type: match artwork.fmt {
+                    mp4ameta::ImgFmt::Png => "image/png",
+                    mp4ameta::ImgFmt::Bmp => "image/bmp",
+                    mp4ameta::ImgFmt::Jpeg => "image/jpeg",
+                }
+                .to_owned(),
+                data: artwork.data.to_owned(),
+            })
+            .collect()
+    }
+
+    fn add_picture(&mut self, picture_type: PictureType, mime_type: String, data: Vec<u8>) {
+        if picture_type == PictureType::Front {
+            self.set_front_cover(Some(Picture {
+                picture_type,
+                mime_type,
+                data,
+            }));
+        }
+    }
+
     fn clear(&mut self) {
         mp4ameta::Tag::clear(self);
     }
@@ -182,3 +284,9 @@ impl Tag for mp4ameta::Tag {
         Ok(())
     }
 }
+
+const M4A_ISRC: &str = "ISRC";
+
+// Not exposed by mp4ameta as named idents
+const M4A_ALBUM_SORT_ORDER: mp4ameta::Fourcc = mp4ameta::Fourcc(*b"soal");
+const M4A_TITLE_SORT_ORDER: mp4ameta::Fourcc = mp4ameta::Fourcc(*b"sonm");
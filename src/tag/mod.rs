@@ -1,6 +1,6 @@
 use std::fmt::{Debug, Formatter};
 use std::fs::File;
-use std::io::{Seek, Write};
+use std::io::{BufReader, Cursor, Read, Seek, Write};
 use std::path::Path;
 
 use anyhow::{Context, Result};
@@ -13,8 +13,11 @@ mod flac;
 pub mod frame;
 mod id3;
 mod m4a;
+mod wv;
 
-pub trait Tag: TagClone {
+pub const ID3V1_TEXT_MAX_LEN: usize = 30;
+
+pub trait Tag: TagClone + Send {
     fn frame_ids(&self) -> Vec<FrameId>;
 
     fn title(&self) -> Option<&str>;
@@ -29,6 +32,8 @@ pub trait Tag: TagClone {
     fn artist(&self) -> Option<&str>;
     fn set_artist(&mut self, artist: Option<String>);
 
+    fn artists(&self) -> Vec<&str>;
+
     fn year(&self) -> Option<i32>;
     fn set_year(&mut self, year: Option<i32>);
 
@@ -47,12 +52,76 @@ pub trait Tag: TagClone {
     fn genre(&self) -> Option<&str>;
     fn set_genre(&mut self, genre: Option<String>);
 
+    // Formats without multi-value support get the genres joined into a single value
+    fn set_genres(&mut self, genres: Vec<String>) {
+        self.set_genre(Some(genres.join("; ")).filter(|v| !v.is_empty()))
+    }
+
+    fn encoded_by(&self) -> Option<&str>;
+    fn set_encoded_by(&mut self, encoded_by: Option<String>);
+
+    fn isrc(&self) -> Option<&str>;
+    fn set_isrc(&mut self, isrc: Option<String>);
+
+    fn album_sort(&self) -> Option<&str>;
+    fn set_album_sort(&mut self, album_sort: Option<String>);
+
+    fn title_sort(&self) -> Option<&str>;
+    fn set_title_sort(&mut self, title_sort: Option<String>);
+
     fn custom_text(&self, key: &str) -> Option<&str>;
     fn set_custom_text(&mut self, key: String, value: Option<String>);
 
+    fn custom_texts(&self, key: &str) -> Vec<&str> {
+        self.custom_text(key).into_iter().collect()
+    }
+
+    // Formats without multi-value support keep only the last value added
+    fn add_custom_text(&mut self, key: String, value: String) {
+        self.set_custom_text(key, Some(value))
+    }
+
+    fn set_lyrics(&mut self, lyrics: Option<String>);
+
+    fn set_front_cover(&mut self, picture: Option<Picture>);
+
+    fn pictures(&self) -> Vec<Picture>;
+    // Formats with a single untyped image keep only the front cover
+    fn add_picture(&mut self, picture_type: PictureType, mime_type: String, data: Vec<u8>);
+
     fn clear(&mut self);
 
     fn write_to(&self, file: &mut File) -> Result<()>;
+
+    // Only MP3 has a legacy tag to append, returns whether any text did not fit into it
+    fn write_id3v1_to(&self, _file: &mut File) -> Result<bool> {
+        Ok(false)
+    }
+
+    // Backends that can write into arbitrary stream do it in memory, others go through temp file
+    fn write_to_vec(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut file = tempfile::tempfile()?;
+        file.write_all(data)?;
+        self.write_to(&mut file)?;
+        file.rewind()?;
+        let mut result = Vec::new();
+        file.read_to_end(&mut result)?;
+        Ok(result)
+    }
+}
+
+pub struct Picture {
+    pub picture_type: PictureType,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum PictureType {
+    Front,
+    Back,
+    Booklet,
+    Other,
 }
 
 pub trait TagClone {
@@ -87,6 +156,10 @@ impl dyn Tag {
             FrameId::Disc => self.disc().map(FrameContent::U32),
             FrameId::TotalDiscs => self.total_discs().map(FrameContent::U32),
             FrameId::Genre => self.genre().map(|v| FrameContent::Str(v.to_owned())),
+            FrameId::EncodedBy => self.encoded_by().map(|v| FrameContent::Str(v.to_owned())),
+            FrameId::Isrc => self.isrc().map(|v| FrameContent::Str(v.to_owned())),
+            FrameId::AlbumSort => self.album_sort().map(|v| FrameContent::Str(v.to_owned())),
+            FrameId::TitleSort => self.title_sort().map(|v| FrameContent::Str(v.to_owned())),
             FrameId::CustomText { key } => self
                 .custom_text(key)
                 .map(|v| FrameContent::Str(v.to_owned())),
@@ -115,6 +188,10 @@ impl dyn Tag {
             FrameId::Disc => self.set_disc(Some(content.as_u32()?)),
             FrameId::TotalDiscs => self.set_total_discs(Some(content.as_u32()?)),
             FrameId::Genre => self.set_genre(Some(content.as_str()?.to_owned())),
+            FrameId::EncodedBy => self.set_encoded_by(Some(content.as_str()?.to_owned())),
+            FrameId::Isrc => self.set_isrc(Some(content.as_str()?.to_owned())),
+            FrameId::AlbumSort => self.set_album_sort(Some(content.as_str()?.to_owned())),
+            FrameId::TitleSort => self.set_title_sort(Some(content.as_str()?.to_owned())),
             FrameId::CustomText { key } => {
                 self.set_custom_text(key.to_owned(), Some(content.as_str()?.to_owned()))
             }
@@ -135,6 +212,10 @@ impl dyn Tag {
             FrameId::Disc => self.set_disc(None),
             FrameId::TotalDiscs => self.set_total_discs(None),
             FrameId::Genre => self.set_genre(None),
+            FrameId::EncodedBy => self.set_encoded_by(None),
+            FrameId::Isrc => self.set_isrc(None),
+            FrameId::AlbumSort => self.set_album_sort(None),
+            FrameId::TitleSort => self.set_title_sort(None),
             FrameId::CustomText { key } => self.set_custom_text(key.to_owned(), None),
         };
     }
@@ -144,12 +225,23 @@ impl dyn Tag {
         self.clear();
 
         for frame_id in other.frame_ids() {
-            let frame_content = other.frame_content(&frame_id);
-            self.set_frame(&frame_id, frame_content)?;
+            self.copy_frame_from(other, &frame_id)?;
         }
 
         Ok(())
     }
+
+    // Frame content holds a single value so the rest of multi-valued custom text is appended
+    #[allow(clippy::borrowed_box)]
+    pub fn copy_frame_from(&mut self, other: &Box<dyn Tag>, id: &FrameId) -> Result<()> {
+        self.set_frame(id, other.frame_content(id))?;
+        if let FrameId::CustomText { key } = id {
+            for value in other.custom_texts(key).into_iter().skip(1) {
+                self.add_custom_text(key.to_owned(), value.to_owned());
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Debug for dyn Tag {
@@ -164,6 +256,22 @@ impl Debug for dyn Tag {
     }
 }
 
+// Formats read_from has a tag backend for
+pub fn is_supported_format(format: &str) -> bool {
+    matches!(format.to_lowercase().as_ref(), "mp3" | "m4a" | "flac" | "wv")
+}
+
+pub fn read_from(reader: &mut (impl Read + Seek), format: &str) -> Result<Option<Box<dyn Tag>>> {
+    let tag: Box<dyn Tag> = match format.to_lowercase().as_ref() {
+        "mp3" => Box::new(::id3::Tag::read_from2(reader)?),
+        "m4a" => Box::new(mp4ameta::Tag::read_from(reader)?),
+        "flac" => Box::new(metaflac::Tag::read_from(reader)?),
+        "wv" => Box::new(wv::read_from(reader)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(tag))
+}
+
 pub fn read_from_path(path: impl AsRef<Path>, format: &str) -> Result<Option<Box<dyn Tag>>> {
     let context = || {
         format!(
@@ -171,16 +279,63 @@ pub fn read_from_path(path: impl AsRef<Path>, format: &str) -> Result<Option<Box
             path.as_ref().display().path_styled()
         )
     };
-    match format.to_lowercase().as_ref() {
-        "mp3" => ::id3::Tag::read_from_path(&path)
-            .map(|v| Some(Box::new(v) as Box<dyn Tag>))
-            .with_context(context),
-        "m4a" => mp4ameta::Tag::read_from_path(&path)
-            .map(|v| Some(Box::new(v) as Box<dyn Tag>))
-            .with_context(context),
-        "flac" => metaflac::Tag::read_from_path(&path)
-            .map(|v| Some(Box::new(v) as Box<dyn Tag>))
-            .with_context(context),
-        _ => Ok(None),
+    let mut file = BufReader::new(File::open(&path).with_context(context)?);
+    read_from(&mut file, format).with_context(context)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    // MPEG frame header followed by some payload, enough for tags to be written around it
+    const AUDIO: &[u8] = &[0xFF, 0xFB, 0x90, 0x00, 1, 2, 3, 4];
+
+    fn id3_tag(title: &str, track_number: u32) -> Box<dyn Tag> {
+        let mut tag: Box<dyn Tag> = Box::new(::id3::Tag::new());
+        tag.set_title(Some(title.to_owned()));
+        tag.set_track_number(Some(track_number));
+        tag
+    }
+
+    #[test]
+    fn id3_tag_round_trips_in_memory() {
+        let data = id3_tag("Title", 3).write_to_vec(AUDIO).unwrap();
+        assert!(data.ends_with(AUDIO));
+
+        let tag = read_from(&mut Cursor::new(&data), "mp3").unwrap().unwrap();
+        assert_eq!(tag.title(), Some("Title"));
+        assert_eq!(tag.track_number(), Some(3));
+    }
+
+    #[test]
+    fn id3_tag_replaces_existing_one_in_memory() {
+        let data = id3_tag("Old", 1).write_to_vec(AUDIO).unwrap();
+        let data = id3_tag("New", 2).write_to_vec(&data).unwrap();
+        assert!(data.ends_with(AUDIO));
+
+        let tag = read_from(&mut Cursor::new(&data), "mp3").unwrap().unwrap();
+        assert_eq!(tag.title(), Some("New"));
+        assert_eq!(tag.track_number(), Some(2));
+    }
+
+    #[test]
+    fn id3_musicbrainz_track_id_is_stored_as_ufid() {
+        let mut tag = id3_tag("Title", 1);
+        tag.set_custom_text("MusicBrainz Track Id".to_owned(), Some("id".to_owned()));
+        let data = tag.write_to_vec(AUDIO).unwrap();
+
+        let id3_tag = ::id3::Tag::read_from2(Cursor::new(&data)).unwrap();
+        assert_eq!(::id3::Tag::extended_texts(&id3_tag).count(), 0);
+        let ufid = ::id3::Tag::unique_file_identifiers(&id3_tag).next().unwrap();
+        assert_eq!(ufid.owner_identifier, "http://musicbrainz.org");
+        assert_eq!(ufid.identifier, b"id");
+
+        let tag = read_from(&mut Cursor::new(&data), "mp3").unwrap().unwrap();
+        assert_eq!(tag.custom_text("MusicBrainz Track Id"), Some("id"));
+        assert!(tag
+            .frame_ids()
+            .iter()
+            .any(|id| id.to_string() == "TXXX:MusicBrainz Track Id"));
     }
 }
@@ -0,0 +1,251 @@
+use lofty::config::WriteOptions;
+use lofty::file::{FileType, TaggedFileExt};
+use lofty::picture::MimeType;
+use lofty::probe::Probe;
+use lofty::tag::{ItemKey, TagExt};
+
+use super::*;
+
+pub fn read_from(reader: &mut (impl Read + Seek)) -> Result<lofty::tag::Tag> {
+    let tagged_file = Probe::new(reader).set_file_type(FileType::WavPack).read()?;
+    Ok(tagged_file
+        .primary_tag()
+        .cloned()
+        .unwrap_or_else(|| lofty::tag::Tag::new(tagged_file.primary_tag_type())))
+}
+
+impl Tag for lofty::tag::Tag {
+    fn frame_ids(&self) -> Vec<FrameId> {
+        self.items()
+            .filter_map(|item| match item.key() {
+                ItemKey::TrackTitle => Some(FrameId::Title),
+                ItemKey::AlbumTitle => Some(FrameId::Album),
+                ItemKey::AlbumArtist => Some(FrameId::AlbumArtist),
+                ItemKey::TrackArtist => Some(FrameId::Artist),
+                ItemKey::Year => Some(FrameId::Year),
+                ItemKey::TrackNumber => Some(FrameId::Track),
+                ItemKey::TrackTotal => Some(FrameId::TotalTracks),
+                ItemKey::DiscNumber => Some(FrameId::Disc),
+                ItemKey::DiscTotal => Some(FrameId::TotalDiscs),
+                ItemKey::Genre => Some(FrameId::Genre),
+                ItemKey::EncodedBy => Some(FrameId::EncodedBy),
+                ItemKey::Isrc => Some(FrameId::Isrc),
+                ItemKey::AlbumTitleSortOrder => Some(FrameId::AlbumSort),
+                ItemKey::TrackTitleSortOrder => Some(FrameId::TitleSort),
+                key => key
+                    .map_key(self.tag_type(), true)
+                    .map(|key| FrameId::CustomText {
+                        key: key.to_owned(),
+                    }),
+            })
+            .collect()
+    }
+
+    fn title(&self) -> Option<&str> {
+        self.get_string(&ItemKey::TrackTitle)
+    }
+
+    fn set_title(&mut self, title: Option<String>) {
+        set_text(self, ItemKey::TrackTitle, title);
+    }
+
+    fn album(&self) -> Option<&str> {
+        self.get_string(&ItemKey::AlbumTitle)
+    }
+
+    fn set_album(&mut self, album: Option<String>) {
+        set_text(self, ItemKey::AlbumTitle, album);
+    }
+
+    fn album_artist(&self) -> Option<&str> {
+        self.get_string(&ItemKey::AlbumArtist)
+    }
+
+    fn set_album_artist(&mut self, album_artist: Option<String>) {
+        set_text(self, ItemKey::AlbumArtist, album_artist);
+    }
+
+    fn artist(&self) -> Option<&str> {
+        self.get_string(&ItemKey::TrackArtist)
+    }
+
+    fn set_artist(&mut self, artist: Option<String>) {
+        set_text(self, ItemKey::TrackArtist, artist);
+    }
+
+    fn artists(&self) -> Vec<&str> {
+        self.get_strings(&ItemKey::TrackArtist).collect()
+    }
+
+    fn year(&self) -> Option<i32> {
+        number(self, ItemKey::Year)
+    }
+
+    fn set_year(&mut self, year: Option<i32>) {
+        set_text(self, ItemKey::Year, year.map(|v| v.to_string()));
+    }
+
+    fn track_number(&self) -> Option<u32> {
+        number(self, ItemKey::TrackNumber)
+    }
+
+    fn set_track_number(&mut self, track: Option<u32>) {
+        set_text(self, ItemKey::TrackNumber, track.map(|v| v.to_string()));
+    }
+
+    fn total_tracks(&self) -> Option<u32> {
+        number(self, ItemKey::TrackTotal)
+    }
+
+    fn set_total_tracks(&mut self, total_tracks: Option<u32>) {
+        set_text(
+            self,
+            ItemKey::TrackTotal,
+            total_tracks.map(|v| v.to_string()),
+        );
+    }
+
+    fn disc(&self) -> Option<u32> {
+        number(self, ItemKey::DiscNumber)
+    }
+
+    fn set_disc(&mut self, disc: Option<u32>) {
+        set_text(self, ItemKey::DiscNumber, disc.map(|v| v.to_string()));
+    }
+
+    fn total_discs(&self) -> Option<u32> {
+        number(self, ItemKey::DiscTotal)
+    }
+
+    fn set_total_discs(&mut self, total_discs: Option<u32>) {
+        set_text(self, ItemKey::DiscTotal, total_discs.map(|v| v.to_string()));
+    }
+
+    fn genre(&self) -> Option<&str> {
+        self.get_string(&ItemKey::Genre)
+    }
+
+    fn set_genre(&mut self, genre: Option<String>) {
+        set_text(self, ItemKey::Genre, genre);
+    }
+
+    fn encoded_by(&self) -> Option<&str> {
+        self.get_string(&ItemKey::EncodedBy)
+    }
+
+    fn set_encoded_by(&mut self, encoded_by: Option<String>) {
+        set_text(self, ItemKey::EncodedBy, encoded_by);
+    }
+
+    fn isrc(&self) -> Option<&str> {
+        self.get_string(&ItemKey::Isrc)
+    }
+
+    fn set_isrc(&mut self, isrc: Option<String>) {
+        set_text(self, ItemKey::Isrc, isrc);
+    }
+
+    fn album_sort(&self) -> Option<&str> {
+        self.get_string(&ItemKey::AlbumTitleSortOrder)
+    }
+
+    fn set_album_sort(&mut self, album_sort: Option<String>) {
+        set_text(self, ItemKey::AlbumTitleSortOrder, album_sort);
+    }
+
+    fn title_sort(&self) -> Option<&str> {
+        self.get_string(&ItemKey::TrackTitleSortOrder)
+    }
+
+    fn set_title_sort(&mut self, title_sort: Option<String>) {
+        set_text(self, ItemKey::TrackTitleSortOrder, title_sort);
+    }
+
+    fn custom_text(&self, key: &str) -> Option<&str> {
+        self.get_string(&ItemKey::from_key(self.tag_type(), key))
+    }
+
+    fn set_custom_text(&mut self, key: String, value: Option<String>) {
+        let key = ItemKey::from_key(self.tag_type(), &key);
+        set_text(self, key, value);
+    }
+
+    fn set_lyrics(&mut self, lyrics: Option<String>) {
+        set_text(self, ItemKey::Lyrics, lyrics);
+    }
+
+    fn set_front_cover(&mut self, picture: Option<Picture>) {
+        self.remove_picture_type(lofty::picture::PictureType::CoverFront);
+        if let Some(picture) = picture {
+            self.push_picture(lofty::picture::Picture::new_unchecked(
+                lofty::picture::PictureType::CoverFront,
+                Some(MimeType::from_str(&picture.mime_type)),
+                None,
+                picture.data,
+            ));
+        }
+    }
+
+    fn pictures(&self) -> Vec<Picture> {
+        lofty::tag::Tag::pictures(self)
+            .iter()
+            .map(|picture| Picture {
+                picture_type: match picture.pic_type() {
+                    lofty::picture::PictureType::CoverFront => PictureType::Front,
+                    lofty::picture::PictureType::CoverBack => PictureType::Back,
+                    lofty::picture::PictureType::Leaflet => PictureType::Booklet,
+                    _ => PictureType::Other,
+                },
+                mime_type: picture
+                    .mime_type()
+                    .map(|v| v.as_str().to_owned())
+                    .unwrap_or_default(),
+                data: picture.data().to_owned(),
+            })
+            .collect()
+    }
+
+    fn add_picture(&mut self, picture_type: PictureType, mime_type: String, data: Vec<u8>) {
+        let picture_type = match picture_type {
+            PictureType::Front => lofty::picture::PictureType::CoverFront,
+            PictureType::Back => lofty::picture::PictureType::CoverBack,
+            PictureType::Booklet => lofty::picture::PictureType::Leaflet,
+            PictureType::Other => lofty::picture::PictureType::Other,
+        };
+        self.push_picture(lofty::picture::Picture::new_unchecked(
+            picture_type,
+            Some(MimeType::from_str(&mime_type)),
+            None,
+            data,
+        ));
+    }
+
+    fn clear(&mut self) {
+        TagExt::clear(self);
+    }
+
+    fn write_to(&self, file: &mut File) -> Result<()> {
+        file.rewind()?;
+        TagExt::save_to(self, file, WriteOptions::default())?;
+        Ok(())
+    }
+
+    fn write_to_vec(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut cursor = Cursor::new(data.to_vec());
+        TagExt::save_to(self, &mut cursor, WriteOptions::default())?;
+        Ok(cursor.into_inner())
+    }
+}
+
+fn set_text(tag: &mut lofty::tag::Tag, key: ItemKey, value: Option<String>) {
+    if let Some(value) = value {
+        tag.insert_text(key, value);
+    } else {
+        tag.remove_key(&key);
+    }
+}
+
+// APEv2 keeps numbers as text and may store them as "3/12"
+fn number<T: std::str::FromStr>(tag: &lofty::tag::Tag, key: ItemKey) -> Option<T> {
+    tag.get_string(&key)?.split('/').next()?.trim().parse().ok()
+}
@@ -1,17 +1,22 @@
+use std::env;
 use std::time::Duration;
 
+use anyhow::{bail, Result};
+use clap::ValueEnum;
 use console::Term;
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use once_cell::sync::Lazy;
 
+use crate::util::console_event::ConsoleEvent;
+
 static mut CONSOLE: Lazy<Console> = Lazy::new(Console::new);
 
 pub fn get() -> &'static Console {
-    unsafe { &CONSOLE }
+    unsafe { &*std::ptr::addr_of!(CONSOLE) }
 }
 
 pub fn get_mut() -> &'static mut Console {
-    unsafe { &mut CONSOLE }
+    unsafe { &mut *std::ptr::addr_of_mut!(CONSOLE) }
 }
 
 #[macro_export]
@@ -21,6 +26,24 @@ macro_rules! console_print {
     })
 }
 
+// Reported as an event in JSON output where plain prints are dropped
+#[macro_export]
+macro_rules! console_warn {
+    ($($arg:tt)*) => ({
+        $crate::util::console::get().warn(&format!($($arg)*));
+    })
+}
+
+// Printed only when asked for with as many -v flags as the level
+#[macro_export]
+macro_rules! console_print_verbose {
+    ($level:expr, $($arg:tt)*) => ({
+        if $crate::util::console::get().verbosity() >= $level {
+            $crate::util::console::get().println(&format!($($arg)*));
+        }
+    })
+}
+
 #[macro_export]
 macro_rules! pb_set_message {
     ($pb:expr, $($arg:tt)*) => ({
@@ -35,20 +58,67 @@ macro_rules! pb_finish_with_message {
     })
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
 pub struct Console {
     term: Term,
     pbs: Vec<ProgressBar>,
+    output_format: OutputFormat,
+    verbosity: u8,
 }
 
 impl Console {
     fn new() -> Self {
+        let term = Term::buffered_stdout();
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        if no_color || !term.is_term() {
+            console::set_colors_enabled(false);
+        }
+        if no_color || !Term::stderr().is_term() {
+            console::set_colors_enabled_stderr(false);
+        }
         Self {
-            term: Term::buffered_stdout(),
+            term,
             pbs: Vec::new(),
+            output_format: OutputFormat::Text,
+            verbosity: 0,
+        }
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    pub fn set_output_format(&mut self, output_format: OutputFormat) {
+        self.output_format = output_format;
+    }
+
+    pub fn verbosity(&self) -> u8 {
+        self.verbosity
+    }
+
+    pub fn set_verbosity(&mut self, verbosity: u8) {
+        self.verbosity = verbosity;
+    }
+
+    pub fn emit(&self, event: &ConsoleEvent) {
+        if self.output_format != OutputFormat::Json {
+            return;
         }
+        #[allow(clippy::unwrap_used)] // Ok to panic if stdout is gone or event is not serializable
+        Term::stdout()
+            .write_line(&serde_json::to_string(event).unwrap())
+            .unwrap()
     }
 
     pub fn println(&self, str: &str) {
+        if self.output_format == OutputFormat::Json {
+            return;
+        }
         match self
             .pbs
             .iter()
@@ -63,6 +133,39 @@ impl Console {
         }
     }
 
+    pub fn warn(&self, str: &str) {
+        if self.output_format == OutputFormat::Json {
+            self.emit(&ConsoleEvent::Warning {
+                message: console::strip_ansi_codes(str).into_owned(),
+            });
+        } else {
+            self.println(str);
+        }
+    }
+
+    // A frontend reading JSON events has no way to answer, so the run fails instead of hanging
+    pub fn ensure_interactive(&self, prompt: &str) -> Result<()> {
+        if self.output_format == OutputFormat::Json {
+            bail!(
+                "Can't ask \"{}\" with JSON output",
+                console::strip_ansi_codes(prompt)
+            );
+        }
+        Ok(())
+    }
+
+    // Prompts would be overdrawn by the active progress bar otherwise
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        match self
+            .pbs
+            .iter()
+            .find(|pb| !pb.is_hidden() && !pb.is_finished())
+        {
+            Some(pb) => pb.suspend(f),
+            None => f(),
+        }
+    }
+
     pub fn new_default_progress_bar(&mut self, len: u64) -> ProgressBar {
         let pb = ProgressBar::new(len);
         pb.set_style(
@@ -76,29 +179,51 @@ impl Console {
         self.configure_progress_bar(pb)
     }
 
-    pub fn new_default_spinner(&mut self) -> ProgressBar {
-        let pb = ProgressBar::new(!0);
+    pub fn new_default_counting_progress_bar(&mut self, len: u64) -> ProgressBar {
+        let pb = ProgressBar::new(len);
         pb.set_style(
             #[allow(clippy::unwrap_used)] // Ok to panic if template is invalid
             ProgressStyle::default_bar()
+                .template("{spinner:.red/yellow} [{elapsed_precise}] [{bar:50.red/yellow}] {pos}/{len} (ETA {eta}) {wide_msg}")
+                .unwrap()
+                .progress_chars(":: ")
                 .tick_strings(TICK_STRINGS)
-                .template("{spinner:.red/yellow} [{elapsed_precise}] {wide_msg}")
-                .unwrap(),
         );
         self.configure_progress_bar(pb)
     }
 
     fn configure_progress_bar(&mut self, pb: ProgressBar) -> ProgressBar {
-        pb.set_draw_target(ProgressDrawTarget::term(
-            self.term.clone(),
-            PROGRESS_REFRESH_RATE,
-        ));
-        pb.enable_steady_tick(PROGRESS_TICK_MS);
+        if self.output_format == OutputFormat::Json || !self.term.is_term() {
+            pb.set_draw_target(ProgressDrawTarget::hidden());
+        } else {
+            pb.set_draw_target(ProgressDrawTarget::term(
+                self.term.clone(),
+                PROGRESS_REFRESH_RATE,
+            ));
+            pb.enable_steady_tick(PROGRESS_TICK_MS);
+        }
         self.pbs.push(pb.clone());
         pb
     }
 }
 
+pub const VERBOSITY_MATCHING: u8 = 1;
+pub const VERBOSITY_HTTP: u8 = 2;
+
 const PROGRESS_REFRESH_RATE: u8 = 15u8;
 const PROGRESS_TICK_MS: Duration = Duration::from_millis(80u64);
 const TICK_STRINGS: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_fail_with_json_output() {
+        let mut console = Console::new();
+        assert!(console.ensure_interactive("Proceed?").is_ok());
+
+        console.set_output_format(OutputFormat::Json);
+        assert!(console.ensure_interactive("Proceed?").is_err());
+    }
+}
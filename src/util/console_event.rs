@@ -0,0 +1,45 @@
+use serde::Serialize;
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum ConsoleEvent {
+    Analyzing {
+        path: String,
+    },
+    Matched {
+        path: String,
+        release_uri: Option<String>,
+        unmatched_paths: Vec<String>,
+    },
+    ChangePlanned {
+        kind: ChangeKind,
+        source: Option<String>,
+        target: String,
+        frames: Vec<FrameDiff>,
+    },
+    Written {
+        path: String,
+    },
+    Warning {
+        message: String,
+    },
+    Error {
+        message: String,
+        causes: Vec<String>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeKind {
+    MusicFile,
+    Cover,
+    Cleanup,
+}
+
+#[derive(Serialize)]
+pub struct FrameDiff {
+    pub frame: String,
+    pub source: Option<String>,
+    pub target: Option<String>,
+}
@@ -0,0 +1,54 @@
+use std::io::Cursor;
+
+use anyhow::{bail, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+
+pub fn compress_to_fit(data: &[u8], max_bytes: u64) -> Result<Vec<u8>> {
+    let mut image = DynamicImage::ImageRgb8(image::load_from_memory(data)?.to_rgb8());
+
+    loop {
+        for quality in (MIN_JPEG_QUALITY..=MAX_JPEG_QUALITY)
+            .rev()
+            .step_by(JPEG_QUALITY_STEP)
+        {
+            let bytes = encode_jpeg(&image, quality)?;
+            if bytes.len() as u64 <= max_bytes {
+                return Ok(bytes);
+            }
+        }
+
+        if image.width() <= MIN_DIMENSION || image.height() <= MIN_DIMENSION {
+            bail!("Failed to fit cover into {} bytes", max_bytes)
+        }
+
+        image = image.resize(
+            image.width() * 3 / 4,
+            image.height() * 3 / 4,
+            FilterType::Lanczos3,
+        );
+    }
+}
+
+fn encode_jpeg(image: &DynamicImage, quality: u8) -> Result<Vec<u8>> {
+    let mut bytes = Cursor::new(Vec::new());
+    JpegEncoder::new_with_quality(&mut bytes, quality).encode_image(image)?;
+    Ok(bytes.into_inner())
+}
+
+pub fn is_jpeg(data: &[u8]) -> bool {
+    image::guess_format(data).is_ok_and(|v| v == ImageFormat::Jpeg)
+}
+
+pub fn jpeg_extension_for(extension: &str) -> &str {
+    match extension.to_lowercase().as_str() {
+        "jpg" | "jpeg" => extension,
+        _ => "jpg",
+    }
+}
+
+const MAX_JPEG_QUALITY: u8 = 90;
+const MIN_JPEG_QUALITY: u8 = 50;
+const JPEG_QUALITY_STEP: usize = 10;
+const MIN_DIMENSION: u32 = 100;
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::util::path_extensions::PathExtensions;
+
+const LYRICS_EXTENSION: &str = "lrc";
+
+pub struct LyricsIndex {
+    paths_by_name: HashMap<String, PathBuf>,
+}
+
+impl LyricsIndex {
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let mut paths_by_name = HashMap::new();
+        for entry in fs::read_dir(dir)
+            .with_context(|| format!("Can't read lyrics folder {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if !path
+                .extension_or_empty()
+                .eq_ignore_ascii_case(LYRICS_EXTENSION)
+            {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|v| v.to_str()) {
+                paths_by_name.insert(name.to_lowercase(), path.clone());
+            }
+        }
+        Ok(LyricsIndex { paths_by_name })
+    }
+
+    // Base file names are tried first as the most precise match, title is the last resort
+    pub fn find(&self, file_paths: &[&Path], title: Option<&str>) -> Option<&Path> {
+        file_paths
+            .iter()
+            .filter_map(|path| path.file_stem().and_then(|v| v.to_str()))
+            .chain(title)
+            .find_map(|name| self.paths_by_name.get(&name.to_lowercase()))
+            .map(PathBuf::as_path)
+    }
+}
@@ -1,5 +1,9 @@
 pub mod audio_file_duration;
 pub mod console;
+pub mod console_event;
 pub mod console_styleable;
+pub mod image_compression;
+pub mod lyrics;
 pub mod path_extensions;
+pub mod playlist;
 pub mod string_extensions;
@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use reqwest::Url;
+
+use crate::console_warn;
+use crate::util::console_styleable::ConsoleStyleable;
+use crate::util::path_extensions::PathExtensions;
+
+pub fn read_m3u(path: &Path) -> Result<Vec<PathBuf>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Can't read playlist {}", path.display()))?;
+    // Relative entries are relative to the playlist itself, absolute ones replace the base
+    let base_path = path.parent_or_empty();
+    let mut result = Vec::new();
+    for line in content.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        // Both comments and extended M3U directives start with '#'
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry_path = if line.starts_with("file://") {
+            // URLs keep special characters percent-encoded ("My%20Song.mp3")
+            let Some(file_path) = Url::parse(line).ok().and_then(|v| v.to_file_path().ok()) else {
+                console_warn!(
+                    "{} {}",
+                    "Skipping invalid playlist entry:".warning_styled(),
+                    line.path_styled()
+                );
+                continue;
+            };
+            file_path
+        } else {
+            base_path.join(line)
+        };
+        if !entry_path.is_file() {
+            console_warn!(
+                "{} {}",
+                "Skipping missing playlist entry:".warning_styled(),
+                entry_path.display().path_styled()
+            );
+            continue;
+        }
+        result.push(entry_path);
+    }
+    Ok(result.into_iter().unique().collect_vec())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_urls_are_percent_decoded() {
+        let dir = tempfile::tempdir().unwrap();
+        let song_path = dir.path().join("My Song #1.mp3");
+        fs::write(&song_path, b"").unwrap();
+        let playlist_path = dir.path().join("playlist.m3u");
+        let url = Url::from_file_path(&song_path).unwrap();
+        fs::write(&playlist_path, format!("#EXTM3U\n{}\n", url)).unwrap();
+
+        assert!(url.as_str().contains("My%20Song%20%231.mp3"));
+        assert_eq!(read_m3u(&playlist_path).unwrap(), vec![song_path]);
+    }
+
+    #[test]
+    fn relative_entries_resolve_against_playlist() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("album")).unwrap();
+        let song_path = dir.path().join("album").join("01.mp3");
+        fs::write(&song_path, b"").unwrap();
+        let playlist_path = dir.path().join("playlist.m3u");
+        fs::write(&playlist_path, "album/01.mp3\nalbum/missing.mp3\n").unwrap();
+
+        assert_eq!(read_m3u(&playlist_path).unwrap(), vec![song_path]);
+    }
+}
@@ -1,28 +1,66 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use clap::ValueEnum;
 use fuzzy_matcher::FuzzyMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
-use strsim::normalized_damerau_levenshtein;
+use strsim::{jaro_winkler, normalized_damerau_levenshtein};
 use unidecode::unidecode;
 
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SimilarityAlgorithm {
+    Levenshtein,
+    Skim,
+    Jaro,
+}
+
+#[derive(Clone, Copy)]
+pub struct SimilarityOptions {
+    pub algorithm: Option<SimilarityAlgorithm>,
+    pub transliterate: bool,
+}
+
+impl Default for SimilarityOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: None,
+            transliterate: true,
+        }
+    }
+}
+
 pub trait StringExtensions {
-    fn simplify(&self) -> String;
-    fn is_similar(&self, other: &str) -> bool;
-    fn similarity_score(&self, other: &str) -> f64;
+    fn simplify(&self, options: &SimilarityOptions) -> String;
+    fn is_similar(&self, other: &str, options: &SimilarityOptions) -> bool;
+    fn similarity_score(&self, other: &str, options: &SimilarityOptions) -> f64;
     fn remove_special_chars(&self) -> String;
     fn remove_excessive_whitespaces(&self) -> String;
+    fn to_title_case(&self) -> String;
+    fn natural_cmp(&self, other: &str) -> Ordering;
 }
 
 impl StringExtensions for str {
-    fn simplify(&self) -> String {
-        unidecode(self)
-            .to_lowercase()
-            .remove_special_chars()
-            .remove_excessive_whitespaces()
+    fn simplify(&self, options: &SimilarityOptions) -> String {
+        if options.transliterate {
+            unidecode(self)
+                .to_lowercase()
+                .remove_special_chars()
+                .remove_excessive_whitespaces()
+        } else {
+            self.to_lowercase()
+                .chars()
+                .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+                .collect::<String>()
+                .remove_excessive_whitespaces()
+        }
     }
 
-    fn is_similar(&self, other: &str) -> bool {
+    fn is_similar(&self, other: &str, options: &SimilarityOptions) -> bool {
         const SIMILAR_SCORE: f64 = 0.85f64;
-        let self_simplified = self.simplify();
-        let other_simplified = other.simplify();
+        const SIMILAR_JARO_SCORE: f64 = 0.9f64;
+        let self_simplified = self.simplify(options);
+        let other_simplified = other.simplify(options);
         let strings_are_similar =
             || normalized_damerau_levenshtein(&self_simplified, &other_simplified) >= SIMILAR_SCORE;
         let strings_are_prefixes_of_each_other = || {
@@ -34,13 +72,28 @@ impl StringExtensions for str {
                     .fuzzy_match(&other_simplified, &self_simplified)
                     .is_some()
         };
-        strings_are_similar() || strings_are_prefixes_of_each_other()
+        match options.algorithm {
+            None => strings_are_similar() || strings_are_prefixes_of_each_other(),
+            Some(SimilarityAlgorithm::Levenshtein) => strings_are_similar(),
+            Some(SimilarityAlgorithm::Skim) => strings_are_prefixes_of_each_other(),
+            Some(SimilarityAlgorithm::Jaro) => {
+                jaro_winkler(&self_simplified, &other_simplified) >= SIMILAR_JARO_SCORE
+            }
+        }
     }
 
-    fn similarity_score(&self, other: &str) -> f64 {
-        let self_simplified = self.simplify();
-        let other_simplified = other.simplify();
-        normalized_damerau_levenshtein(&self_simplified, &other_simplified)
+    fn similarity_score(&self, other: &str, options: &SimilarityOptions) -> f64 {
+        let self_simplified = self.simplify(options);
+        let other_simplified = other.simplify(options);
+        match options.algorithm {
+            None | Some(SimilarityAlgorithm::Levenshtein) => {
+                normalized_damerau_levenshtein(&self_simplified, &other_simplified)
+            }
+            Some(SimilarityAlgorithm::Skim) => SkimMatcherV2::default()
+                .fuzzy_match(&self_simplified, &other_simplified)
+                .unwrap_or_default() as f64,
+            Some(SimilarityAlgorithm::Jaro) => jaro_winkler(&self_simplified, &other_simplified),
+        }
     }
 
     fn remove_special_chars(&self) -> String {
@@ -52,4 +105,175 @@ impl StringExtensions for str {
     fn remove_excessive_whitespaces(&self) -> String {
         self.replace("  ", " ")
     }
+
+    fn to_title_case(&self) -> String {
+        let words = self.split(' ').collect::<Vec<_>>();
+        let last_index = words.len().saturating_sub(1);
+        // In an all caps input every word looks like an abbreviation, so none is taken as one
+        let is_all_caps =
+            self.chars().any(char::is_alphabetic) && !self.chars().any(char::is_lowercase);
+        words
+            .iter()
+            .enumerate()
+            .map(|(index, word)| {
+                let bare_word = word.trim_matches(|c: char| !c.is_alphanumeric() && c != '.');
+                if is_roman_numeral(bare_word) {
+                    word.to_uppercase()
+                } else if index != 0
+                    && index != last_index
+                    && !starts_subtitle(words[index - 1])
+                    && TITLE_CASE_SMALL_WORDS.contains(&bare_word.to_lowercase().as_str())
+                {
+                    word.to_lowercase()
+                } else if is_stylized(bare_word, is_all_caps) {
+                    word.to_string()
+                } else {
+                    capitalize(word)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // Compares digit runs by value so "2" goes before "10", falls back to plain order on ties
+    fn natural_cmp(&self, other: &str) -> Ordering {
+        let mut self_chars = self.chars().peekable();
+        let mut other_chars = other.chars().peekable();
+        loop {
+            let ordering = match (self_chars.peek(), other_chars.peek()) {
+                (None, None) => return self.cmp(other),
+                (None, Some(_)) => return Ordering::Less,
+                (Some(_), None) => return Ordering::Greater,
+                (Some(a), Some(b)) if a.is_ascii_digit() && b.is_ascii_digit() => {
+                    let a = take_number(&mut self_chars);
+                    let b = take_number(&mut other_chars);
+                    a.len().cmp(&b.len()).then_with(|| a.cmp(&b))
+                }
+                (Some(a), Some(b)) => {
+                    let ordering = a.cmp(b);
+                    self_chars.next();
+                    other_chars.next();
+                    ordering
+                }
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        number.push(c);
+    }
+    number.trim_start_matches('0').to_owned()
+}
+
+const TITLE_CASE_SMALL_WORDS: [&str; 16] = [
+    "a", "an", "and", "as", "at", "but", "by", "feat.", "for", "in", "nor", "of", "on", "or",
+    "the", "to",
+];
+
+// Words after "-" or ":" start a subtitle and are capitalized like the first word
+fn starts_subtitle(previous_word: &str) -> bool {
+    previous_word.ends_with(':') || !previous_word.chars().any(char::is_alphanumeric)
+}
+
+fn is_roman_numeral(word: &str) -> bool {
+    // Only small numerals, longer ones clash with regular words like "mix" or "dim"
+    !word.is_empty()
+        && [
+            "I", "II", "III", "IV", "V", "VI", "VII", "VIII", "IX", "X", "XI", "XII",
+        ]
+        .contains(&word.to_uppercase().as_str())
+}
+
+// Abbreviations like "R.E.M.", "AC/DC", "ABBA" and names like "McCartney" are kept as is
+fn is_stylized(word: &str, is_all_caps: bool) -> bool {
+    let inner = word.trim_end_matches('.');
+    let letters = inner
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .collect::<Vec<_>>();
+    inner.contains(['.', '/'])
+        || !is_all_caps && letters.len() > 1 && letters.iter().all(|c| c.is_uppercase())
+        || inner.chars().skip(1).any(|c| c.is_uppercase())
+            && inner.chars().any(|c| c.is_lowercase())
+}
+
+fn capitalize(word: &str) -> String {
+    let mut capitalized = false;
+    word.chars()
+        .map(|c| {
+            if !capitalized && c.is_alphanumeric() {
+                capitalized = true;
+                c.to_uppercase().collect::<String>()
+            } else {
+                c.to_lowercase().collect::<String>()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_cmp_orders_numbers_by_value() {
+        let mut names = vec!["10.flac", "2.flac", "1.flac", "01 b.flac", "01 a.flac"];
+        names.sort_by(|a, b| a.natural_cmp(b));
+        assert_eq!(
+            names,
+            vec!["01 a.flac", "01 b.flac", "1.flac", "2.flac", "10.flac"]
+        );
+    }
+
+    #[test]
+    fn natural_cmp_compares_text_parts() {
+        assert_eq!("Track 9".natural_cmp("Track 10"), Ordering::Less);
+        assert_eq!("a10".natural_cmp("b2"), Ordering::Less);
+        assert_eq!("abc".natural_cmp("abc"), Ordering::Equal);
+        assert_eq!("abc".natural_cmp("ab"), Ordering::Greater);
+    }
+
+    #[test]
+    fn title_case_keeps_abbreviations() {
+        assert_eq!("AC/DC".to_title_case(), "AC/DC");
+        assert_eq!("R.E.M.".to_title_case(), "R.E.M.");
+        assert_eq!("songs by R.E.M.".to_title_case(), "Songs by R.E.M.");
+        assert_eq!("ABBA gold".to_title_case(), "ABBA Gold");
+        assert_eq!("mixed by DJ shadow".to_title_case(), "Mixed by DJ Shadow");
+        assert_eq!("paul McCartney".to_title_case(), "Paul McCartney");
+        assert_eq!("SONGS BY R.E.M.".to_title_case(), "Songs by R.E.M.");
+    }
+
+    #[test]
+    fn title_case_lowercases_small_words_inside() {
+        assert_eq!(
+            "THE END OF THE WORLD".to_title_case(),
+            "The End of the World"
+        );
+        assert_eq!(
+            "the end of the world".to_title_case(),
+            "The End of the World"
+        );
+        assert_eq!("song FEAT. artist".to_title_case(), "Song feat. Artist");
+        assert_eq!("what it is for".to_title_case(), "What It Is For");
+        assert_eq!(
+            "part one - the beginning".to_title_case(),
+            "Part One - The Beginning"
+        );
+    }
+
+    #[test]
+    fn title_case_uppercases_roman_numerals() {
+        assert_eq!("rocky ii".to_title_case(), "Rocky II");
+        assert_eq!(
+            "volume iv: the return".to_title_case(),
+            "Volume IV: The Return"
+        );
+    }
 }